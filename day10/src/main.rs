@@ -1,93 +1,371 @@
-use aochelpers::{get_daily_input, parse_number_grid, Coordinate};
+use aochelpers::{get_daily_input, Coordinate};
 use code_timing_macros::time_function;
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::error::Error;
 
+// What counts as a trail: it starts at height `start`, ends on the first
+// cell of height `end`, and each step changes the height by one of `steps`.
+// Cells marked with one of `impassable` can't be walked on at all.
+#[derive(Debug, Clone, PartialEq)]
+struct Rules {
+    steps: Vec<i32>,
+    start: i32,
+    end: i32,
+    impassable: Vec<char>,
+}
+
+impl Default for Rules {
+    // the puzzle's: uphill a step at a time from 0 to 9
+    fn default() -> Rules {
+        Rules {
+            steps: vec![1],
+            start: 0,
+            end: 9,
+            impassable: vec!['.'],
+        }
+    }
+}
+
 struct Map {
-    grid: HashMap<Coordinate<i32>, char>,
+    grid: HashMap<Coordinate<i32>, i32>,
+    rules: Rules,
 }
 
 type Path = Vec<Coordinate<i32>>;
 
+// A fixed-size set of small integers, one bit each.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Bitset {
+    words: Vec<u64>,
+}
+
+impl Bitset {
+    fn new(size: usize) -> Bitset {
+        Bitset {
+            words: vec![0; size.div_ceil(64)],
+        }
+    }
+
+    fn insert(&mut self, i: usize) {
+        self.words[i / 64] |= 1 << (i % 64);
+    }
+
+    fn union_with(&mut self, other: &Bitset) {
+        for (word, theirs) in self.words.iter_mut().zip(&other.words) {
+            *word |= theirs;
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+}
+
 impl Map {
     fn count_trailheads(&self) -> usize {
-        self.grid.iter().filter(|(_, &c)| c == '0').count()
+        self.grid
+            .values()
+            .filter(|&&h| h == self.rules.start)
+            .count()
     }
 
     fn trailheads(&self) -> Vec<Coordinate<i32>> {
         self.grid
             .iter()
-            .filter(|(_, &c)| c == '0')
-            .map(|(c, _)| c.clone())
+            .filter(|(_, &h)| h == self.rules.start)
+            .map(|(c, _)| *c)
             .collect()
     }
 
-    fn paths_from(&self, trailhead: Coordinate<i32>) -> Vec<Path> {
-        let mut paths: Vec<Path> = Vec::new();
-        let mut level = self.grid[&trailhead];
-        paths.push(vec![trailhead]);
+    // where a trail at `cell` can go next
+    fn moves(&self, cell: Coordinate<i32>) -> impl Iterator<Item = Coordinate<i32>> + '_ {
+        let height = self.grid[&cell];
+        let finished = height == self.rules.end;
+        cell.neighbours().into_iter().filter(move |c| {
+            !finished
+                && self
+                    .grid
+                    .get(c)
+                    .is_some_and(|h| self.rules.steps.contains(&(h - height)))
+        })
+    }
+}
 
-        while level < '9' {
-            let mut new_paths = Vec::new();
-            for path in paths.into_iter() {
-                let neighbours =
-                    path.last().unwrap().neighbours().into_iter().filter(|c| {
-                        match self.grid.get(c) {
-                            Some(ch) if *ch == (level as u8 + 1) as char => true,
-                            _ => false,
-                        }
-                    });
-                for neighbour in neighbours.into_iter() {
-                    if self.grid.get(&neighbour) == None {
-                        continue;
+// Groups the cells into strongly connected components, with each component
+// coming after every component it can reach.  Tarjan's algorithm, with an
+// explicit stack so big maps don't overflow the real one.
+fn components(edges: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    const UNSEEN: usize = usize::MAX;
+    let mut index = vec![UNSEEN; edges.len()];
+    let mut low = vec![0; edges.len()];
+    let mut on_stack = vec![false; edges.len()];
+    let mut stack = Vec::new();
+    let mut next = 0;
+    let mut found = Vec::new();
+    for root in 0..edges.len() {
+        if index[root] != UNSEEN {
+            continue;
+        }
+        // (cell, which of its edges to look at next)
+        let mut work = vec![(root, 0)];
+        while let Some((v, e)) = work.pop() {
+            if e == 0 {
+                index[v] = next;
+                low[v] = next;
+                next += 1;
+                stack.push(v);
+                on_stack[v] = true;
+            }
+            if let Some(&w) = edges[v].get(e) {
+                work.push((v, e + 1));
+                if index[w] == UNSEEN {
+                    work.push((w, 0));
+                } else if on_stack[w] {
+                    low[v] = low[v].min(index[w]);
+                }
+                continue;
+            }
+            if low[v] == index[v] {
+                let mut component = Vec::new();
+                while let Some(w) = stack.pop() {
+                    on_stack[w] = false;
+                    component.push(w);
+                    if w == v {
+                        break;
                     }
-                    let mut new_path = path.clone();
-                    new_path.push(neighbour);
-                    new_paths.push(new_path);
                 }
+                found.push(component);
+            }
+            if let Some(&(u, _)) = work.last() {
+                low[u] = low[u].min(low[v]);
             }
-            paths = new_paths;
-            level = (level as u8 + 1) as char;
         }
-        paths
     }
+    found
 }
 
-fn parse_map(data: &str) -> Map {
-    let grid = parse_number_grid(data);
-    Map { grid }
+// For every cell: how many distinct trails lead from it to a summit, and which
+// summits those are.  Only uphill steps make the moves acyclic, so this works
+// on components of cells that can reach each other instead: a component is
+// done after everything it leads to, and if it has a loop in it then any trail
+// through it can go round as often as it likes.
+struct Trails<'a> {
+    map: &'a Map,
+    cells: HashMap<Coordinate<i32>, usize>,
+    component: Vec<usize>,
+    // None when there are infinitely many
+    ratings: Vec<Option<u64>>,
+    summits: Vec<Bitset>,
 }
 
-#[time_function]
-fn part1(data: &str) -> usize {
-    let map = parse_map(data);
-    let mut sum = 0;
-    for trailhead in map.trailheads() {
-        let mut finals: HashSet<Coordinate<i32>> = HashSet::new();
-        let paths = map.paths_from(trailhead);
-        for path in paths {
-            finals.insert(path.last().unwrap().clone());
+impl<'a> Trails<'a> {
+    fn new(map: &'a Map) -> Trails<'a> {
+        let coords: Vec<Coordinate<i32>> = map.grid.keys().copied().collect();
+        let cells: HashMap<Coordinate<i32>, usize> =
+            coords.iter().enumerate().map(|(i, c)| (*c, i)).collect();
+        let edges: Vec<Vec<usize>> = coords
+            .iter()
+            .map(|&c| map.moves(c).map(|next| cells[&next]).collect())
+            .collect();
+        let mut peaks = HashMap::new();
+        for &c in &coords {
+            if map.grid[&c] == map.rules.end {
+                let n = peaks.len();
+                peaks.insert(cells[&c], n);
+            }
+        }
+
+        let mut trails = Trails {
+            map,
+            cells,
+            component: vec![0; coords.len()],
+            ratings: Vec::new(),
+            summits: Vec::new(),
+        };
+        for (n, members) in components(&edges).into_iter().enumerate() {
+            for &v in &members {
+                trails.component[v] = n;
+            }
+            let mut summits = Bitset::new(peaks.len());
+            let mut rating = Some(0);
+            for &v in &members {
+                if let Some(&peak) = peaks.get(&v) {
+                    summits.insert(peak);
+                    rating = rating.map(|r| r + 1);
+                }
+                for &w in edges[v].iter().filter(|&&w| trails.component[w] != n) {
+                    let below = trails.component[w];
+                    summits.union_with(&trails.summits[below]);
+                    rating = rating.zip(trails.ratings[below]).map(|(a, b)| a + b);
+                }
+            }
+            if members.len() > 1 && summits.len() > 0 {
+                rating = None;
+            }
+            trails.ratings.push(rating);
+            trails.summits.push(summits);
+        }
+        trails
+    }
+
+    fn component_of(&self, cell: Coordinate<i32>) -> usize {
+        self.component[self.cells[&cell]]
+    }
+
+    // how many summits can be reached
+    fn score(&self, trailhead: Coordinate<i32>) -> usize {
+        self.summits[self.component_of(trailhead)].len()
+    }
+
+    // how many ways there are to reach them
+    fn rating(&self, trailhead: Coordinate<i32>) -> Option<u64> {
+        self.ratings[self.component_of(trailhead)]
+    }
+
+    // Every trail from `trailhead` that doesn't visit a cell twice, one at a
+    // time.  Cells that can't reach a summit are never stepped on, so no work
+    // is wasted on dead ends.
+    fn trails_from(&self, trailhead: Coordinate<i32>) -> impl Iterator<Item = Path> + '_ {
+        let leads_somewhere = |cell| self.summits[self.component_of(cell)].len() > 0;
+        let mut stack: Vec<Path> = Vec::new();
+        if leads_somewhere(trailhead) {
+            stack.push(vec![trailhead]);
         }
-        sum += finals.len();
+        std::iter::from_fn(move || {
+            while let Some(path) = stack.pop() {
+                let last = *path.last().unwrap();
+                if self.map.grid[&last] == self.map.rules.end {
+                    return Some(path);
+                }
+                for next in self.map.moves(last) {
+                    if leads_somewhere(next) && !path.contains(&next) {
+                        let mut longer = path.clone();
+                        longer.push(next);
+                        stack.push(longer);
+                    }
+                }
+            }
+            None
+        })
     }
-    sum
 }
 
-#[time_function]
-fn part2(data: &str) -> usize {
-    let map = parse_map(data);
-    let mut sum = 0;
-    for trailhead in map.trailheads() {
-        let paths = map.paths_from(trailhead);
-        sum += paths.len();
+// Heights are either one digit per cell, or, if any line has spaces in it,
+// whitespace-separated integers.
+fn parse_map(data: &str, rules: Rules) -> Result<Map, String> {
+    let spaced = data
+        .lines()
+        .any(|line| line.trim().contains(char::is_whitespace));
+    let mut grid = HashMap::new();
+    for (y, line) in data.lines().enumerate() {
+        let tokens: Vec<&str> = if spaced {
+            line.split_whitespace().collect()
+        } else {
+            line.trim().split("").filter(|t| !t.is_empty()).collect()
+        };
+        for (x, token) in tokens.into_iter().enumerate() {
+            let mut chars = token.chars();
+            if let (Some(c), None) = (chars.next(), chars.next()) {
+                if rules.impassable.contains(&c) {
+                    continue;
+                }
+            }
+            let height = token
+                .parse()
+                .map_err(|_| format!("can't read height {:?} at ({}, {})", token, x, y))?;
+            grid.insert(
+                Coordinate {
+                    x: x as i32,
+                    y: y as i32,
+                },
+                height,
+            );
+        }
     }
-    sum
+    Ok(Map { grid, rules })
+}
+
+fn total_score(map: &Map) -> usize {
+    let trails = Trails::new(map);
+    map.trailheads().into_iter().map(|t| trails.score(t)).sum()
+}
+
+fn total_rating(map: &Map) -> Option<u64> {
+    let trails = Trails::new(map);
+    map.trailheads().into_iter().map(|t| trails.rating(t)).sum()
+}
+
+#[time_function]
+fn part1(data: &str) -> Result<usize, String> {
+    Ok(total_score(&parse_map(data, Rules::default())?))
+}
+
+#[time_function]
+fn part2(data: &str) -> Result<u64, String> {
+    let map = parse_map(data, Rules::default())?;
+    // uphill only, so never a loop
+    Ok(total_rating(&map).expect("finitely many trails"))
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     let data = get_daily_input(10, 2024)?;
-    println!("part1: {}", part1(&data));
-    println!("part2: {}", part2(&data));
+    println!("part1: {}", part1(&data)?);
+    println!("part2: {}", part2(&data)?);
+
+    let args: Vec<String> = std::env::args().collect();
+    let flag = |name: &str| {
+        args.iter()
+            .position(|arg| arg == name)
+            .and_then(|i| args.get(i + 1))
+    };
+
+    // e.g. `--steps -1,1,2 --start 0 --end 9 --impassable .#` walks the map
+    // by other rules
+    let mut rules = Rules::default();
+    let mut changed = false;
+    if let Some(steps) = flag("--steps") {
+        rules.steps = steps
+            .split(',')
+            .map(|step| step.parse())
+            .collect::<Result<_, _>>()?;
+        changed = true;
+    }
+    if let Some(start) = flag("--start") {
+        rules.start = start.parse()?;
+        changed = true;
+    }
+    if let Some(end) = flag("--end") {
+        rules.end = end.parse()?;
+        changed = true;
+    }
+    if let Some(markers) = flag("--impassable") {
+        rules.impassable = markers.chars().collect();
+        changed = true;
+    }
+    let map = parse_map(&data, rules)?;
+    if changed {
+        let rating = match total_rating(&map) {
+            Some(rating) => rating.to_string(),
+            None => "infinite".to_string(),
+        };
+        println!("score: {}, rating: {}", total_score(&map), rating);
+    }
+
+    // `--trails N` lists the first N trails without finding the rest
+    if let Some(n) = flag("--trails") {
+        let n: usize = n.parse()?;
+        let trails = Trails::new(&map);
+        let mut heads = map.trailheads();
+        heads.sort_by_key(|c| (c.y, c.x));
+        for trail in heads
+            .into_iter()
+            .flat_map(|head| trails.trails_from(head))
+            .take(n)
+        {
+            let steps: Vec<String> = trail.iter().map(|c| format!("({},{})", c.x, c.y)).collect();
+            println!("{}", steps.join(" "));
+        }
+    }
 
     Ok(())
 }
@@ -106,16 +384,133 @@ mod tests {
 
     #[test]
     fn test_parse() {
-        assert_eq!(parse_map(&TESTDATA).count_trailheads(), 9);
+        assert_eq!(
+            parse_map(TESTDATA, Rules::default())
+                .unwrap()
+                .count_trailheads(),
+            9
+        );
     }
 
     #[test]
     fn test_part1() {
-        assert_eq!(part1(&TESTDATA), 36);
+        assert_eq!(part1(&TESTDATA).unwrap(), 36);
     }
 
     #[test]
     fn test_part2() {
-        assert_eq!(part2(&TESTDATA), 81);
+        assert_eq!(part2(&TESTDATA).unwrap(), 81);
+    }
+
+    #[test]
+    fn test_trails() {
+        let map = parse_map(TESTDATA, Rules::default()).unwrap();
+        let trails = Trails::new(&map);
+        let head = Coordinate { x: 2, y: 0 };
+        assert_eq!(trails.score(head), 5);
+        assert_eq!(trails.rating(head), Some(20));
+        let found: Vec<Path> = trails.trails_from(head).collect();
+        assert_eq!(found.len(), 20);
+        for trail in &found {
+            assert_eq!(trail.len(), 10);
+            assert!(trail
+                .windows(2)
+                .all(|w| w[0].manhattan_distance(&w[1]) == 1
+                    && map.grid[&w[1]] == map.grid[&w[0]] + 1));
+        }
+    }
+
+    #[test]
+    fn test_branching() {
+        // every step can go right or down, so 2^9 trails from the corner
+        let data: String = (0..10)
+            .map(|y| {
+                (0..10)
+                    .map(|x| {
+                        if x + y <= 9 {
+                            char::from_digit(x + y, 10).unwrap()
+                        } else {
+                            '.'
+                        }
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_eq!(part2(&data).unwrap(), 512);
+        assert_eq!(part1(&data).unwrap(), 10);
+    }
+
+    #[test]
+    fn test_impassable() {
+        let data = "...0...
+...1...
+...2...
+6543456
+7.....7
+8.....8
+9.....9";
+        assert_eq!(part1(data).unwrap(), 2);
+        let data = data.replace('.', "#");
+        assert!(part1(&data).is_err());
+        let rules = Rules {
+            impassable: vec!['#'],
+            ..Rules::default()
+        };
+        assert_eq!(total_score(&parse_map(&data, rules).unwrap()), 2);
+    }
+
+    #[test]
+    fn test_multi_digit() {
+        let data = "10 11 12
+ . 20 13
+ . 15 14";
+        let rules = Rules {
+            start: 10,
+            end: 15,
+            ..Rules::default()
+        };
+        let map = parse_map(data, rules).unwrap();
+        assert_eq!(map.count_trailheads(), 1);
+        assert_eq!(map.grid.len(), 7);
+        assert_eq!(total_score(&map), 1);
+        assert_eq!(total_rating(&map), Some(1));
+    }
+
+    #[test]
+    fn test_steps() {
+        let data = "0 1
+2 3";
+        let rules = Rules {
+            steps: vec![1, 2],
+            end: 3,
+            ..Rules::default()
+        };
+        let map = parse_map(data, rules).unwrap();
+        assert_eq!(total_rating(&map), Some(2));
+        let data = "0 1
+1 2";
+        let rules = Rules {
+            steps: vec![-1, 1],
+            end: 2,
+            ..Rules::default()
+        };
+        let map = parse_map(data, rules).unwrap();
+        // 0 -> 1 -> 0 -> 1 -> ... before heading up
+        assert_eq!(total_score(&map), 1);
+        assert_eq!(total_rating(&map), None);
+        let trails = Trails::new(&map);
+        let found: Vec<Path> = trails.trails_from(Coordinate { x: 0, y: 0 }).collect();
+        assert_eq!(found.len(), 2);
+        // downhill from the summit doesn't count
+        let rules = Rules {
+            steps: vec![-1, 1],
+            start: 2,
+            end: 0,
+            ..Rules::default()
+        };
+        let map = parse_map(data, rules).unwrap();
+        assert_eq!(total_rating(&map), None);
+        assert_eq!(total_score(&map), 1);
     }
 }
@@ -1,18 +1,117 @@
 use aochelpers::get_daily_input;
 use code_timing_macros::time_function;
+use num_bigint::{BigInt, BigUint};
+use num_traits::{One, ToPrimitive, Zero};
 use std::collections::HashMap;
 use std::error::Error;
+use std::fmt;
 
-fn blink(i: u64) -> Vec<u64> {
-    if i == 0 {
-        vec![1]
-    } else {
-        let s = i.to_string();
-        let l = s.len();
-        if l % 2 == 0 {
-            vec![s[..l / 2].parse().unwrap(), s[l / 2..].parse().unwrap()]
-        } else {
-            vec![i * 2024]
+// Stones are u128s, and a rule that would make one too big says so rather
+// than wrapping.
+type Stone = u128;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum StoneError {
+    Overflow { stone: Stone, rule: String },
+    // too many different stones to build a matrix over
+    TooManyValues(usize),
+}
+
+impl fmt::Display for StoneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StoneError::Overflow { stone, rule } => {
+                write!(f, "{} overflows applying {}", stone, rule)
+            }
+            StoneError::TooManyValues(limit) => {
+                write!(f, "more than {} different stones", limit)
+            }
+        }
+    }
+}
+
+impl Error for StoneError {}
+
+// One rule for what a stone turns into when you blink: the first rule whose
+// test the stone passes says what replaces it.
+struct Rule {
+    name: String,
+    applies: Box<dyn Fn(Stone) -> bool>,
+    transform: Box<dyn Fn(Stone) -> Option<Vec<Stone>>>,
+}
+
+struct Rules {
+    rules: Vec<Rule>,
+}
+
+fn digits(i: Stone) -> u32 {
+    i.checked_ilog10().unwrap_or(0) + 1
+}
+
+fn split_digits(i: Stone) -> Option<Vec<Stone>> {
+    let half = (10 as Stone).pow(digits(i) / 2);
+    Some(vec![i / half, i % half])
+}
+
+impl Rules {
+    // the puzzle's: 0 becomes 1, an even number of digits splits in two, and
+    // anything else is multiplied by 2024
+    fn standard() -> Rules {
+        Rules::parse("zero:1,even-digits:split,any:*2024").unwrap()
+    }
+
+    // Rules written as `test:result` pairs, in order, e.g. `zero:1,any:*3`.
+    // Tests are zero, even, odd, even-digits, odd-digits and any; results are
+    // a number, split (into the two halves of its digits), *n and +n.
+    fn parse(spec: &str) -> Result<Rules, String> {
+        let mut rules = Vec::new();
+        for rule in spec.split(',') {
+            let (test, result) = rule
+                .split_once(':')
+                .ok_or(format!("rule {:?} needs a test and a result", rule))?;
+            let applies: Box<dyn Fn(Stone) -> bool> = match test.trim() {
+                "zero" => Box::new(|i| i == 0),
+                "even" => Box::new(|i| i % 2 == 0),
+                "odd" => Box::new(|i| i % 2 == 1),
+                "even-digits" => Box::new(|i| digits(i).is_multiple_of(2)),
+                "odd-digits" => Box::new(|i| digits(i) % 2 == 1),
+                "any" => Box::new(|_| true),
+                _ => return Err(format!("unknown test {:?}", test)),
+            };
+            let result = result.trim();
+            let number = |n: &str| {
+                n.parse::<Stone>()
+                    .map_err(|_| format!("bad number in {:?}", rule))
+            };
+            let transform: Box<dyn Fn(Stone) -> Option<Vec<Stone>>> = if result == "split" {
+                Box::new(split_digits)
+            } else if let Some(n) = result.strip_prefix('*') {
+                let n = number(n)?;
+                Box::new(move |i: Stone| i.checked_mul(n).map(|i| vec![i]))
+            } else if let Some(n) = result.strip_prefix('+') {
+                let n = number(n)?;
+                Box::new(move |i: Stone| i.checked_add(n).map(|i| vec![i]))
+            } else {
+                let n = number(result)?;
+                Box::new(move |_| Some(vec![n]))
+            };
+            rules.push(Rule {
+                name: rule.trim().to_string(),
+                applies,
+                transform,
+            });
+        }
+        Ok(Rules { rules })
+    }
+
+    // a stone no rule applies to stays as it is
+    fn blink(&self, i: Stone) -> Result<Vec<Stone>, StoneError> {
+        match self.rules.iter().find(|rule| (rule.applies)(i)) {
+            Some(rule) => (rule.transform)(i).ok_or_else(|| StoneError::Overflow {
+                stone: i,
+                rule: rule.name.clone(),
+            }),
+            None => Ok(vec![i]),
         }
     }
 }
@@ -27,39 +126,340 @@ fn blink(i: u64) -> Vec<u64> {
 //     }
 // }
 
-struct Oracle {
-    cache: HashMap<(u64, u64), usize>,
+// Counts the stones one stone turns into, remembering every (stone, blinks)
+// it's worked out along the way.
+struct Oracle<'a> {
+    rules: &'a Rules,
+    cache: HashMap<(Stone, u64), BigUint>,
 }
 
-impl Oracle {
-    fn new() -> Self {
+impl<'a> Oracle<'a> {
+    fn new(rules: &'a Rules) -> Self {
         Self {
+            rules,
             cache: HashMap::new(),
         }
     }
-    fn blink(&mut self, i: u64) -> usize {
-        if self.cache.contains_key(&(i, 1)) {
-            self.cache[&(i, 1)]
+
+    fn run(&mut self, i: Stone, n: u64) -> Result<BigUint, StoneError> {
+        if n == 0 {
+            Ok(BigUint::one())
+        } else if let Some(res) = self.cache.get(&(i, n)) {
+            Ok(res.clone())
         } else {
-            let res = blink(i).len();
-            self.cache.insert((i, 1), res);
-            res
+            let mut res = BigUint::zero();
+            for i in self.rules.blink(i)? {
+                res += self.run(i, n - 1)?;
+            }
+            self.cache.insert((i, n), res.clone());
+            Ok(res)
         }
     }
+}
 
-    fn run(&mut self, i: u64, n: u64) -> usize {
-        if n == 0 {
-            1
-        } else if self.cache.contains_key(&(i, n)) {
-            self.cache[&(i, n)]
-        } else if n == 1 {
-            self.blink(i)
+// Order never matters, so all the stones with the same number can blink
+// together: keep how many of each there are.
+fn simulate(
+    rules: &Rules,
+    stones: &[Stone],
+    n: u64,
+) -> Result<HashMap<Stone, BigUint>, StoneError> {
+    let mut counts: HashMap<Stone, BigUint> = HashMap::new();
+    for &stone in stones {
+        *counts.entry(stone).or_default() += 1u32;
+    }
+    for _ in 0..n {
+        let mut next: HashMap<Stone, BigUint> = HashMap::with_capacity(counts.len());
+        for (stone, count) in counts {
+            for i in rules.blink(stone)? {
+                *next.entry(i).or_default() += &count;
+            }
+        }
+        counts = next;
+    }
+    Ok(counts)
+}
+
+// Every number the stones can ever show, if there are no more than `limit`.
+fn reachable(rules: &Rules, stones: &[Stone], limit: usize) -> Result<Vec<Stone>, StoneError> {
+    let mut seen: Vec<Stone> = Vec::new();
+    let mut index: HashMap<Stone, usize> = HashMap::new();
+    let mut todo = stones.to_vec();
+    while let Some(stone) = todo.pop() {
+        if index.contains_key(&stone) {
+            continue;
+        }
+        if seen.len() == limit {
+            return Err(StoneError::TooManyValues(limit));
+        }
+        index.insert(stone, seen.len());
+        seen.push(stone);
+        todo.extend(rules.blink(stone)?);
+    }
+    Ok(seen)
+}
+
+// Blinking as a matrix over every number the stones can show.  Each stone
+// turns into at most a couple of others, so rows[i] just lists the columns of
+// row i that aren't zero, once for each stone i turns into.
+#[derive(Debug, Clone, PartialEq)]
+struct Matrix {
+    rows: Vec<Vec<usize>>,
+}
+
+impl Matrix {
+    // the counts of each number after one more blink
+    fn step(&self, counts: &[BigUint]) -> Vec<BigUint> {
+        let mut next = vec![BigUint::zero(); counts.len()];
+        for (row, count) in self.rows.iter().zip(counts) {
+            if count.is_zero() {
+                continue;
+            }
+            for &j in row {
+                next[j] += count;
+            }
+        }
+        next
+    }
+}
+
+// There are only so many different numbers the stones ever show (a few
+// thousand from a real input), so blinking is a linear map on how many of
+// each there are.
+const MATRIX_LIMIT: usize = 10_000;
+
+// The total number of stones after 0, 1, 2, ... blinks, `len` of them.
+fn totals(matrix: &Matrix, counts: &[BigUint], len: usize) -> Vec<BigUint> {
+    let mut counts = counts.to_vec();
+    let mut totals = Vec::with_capacity(len);
+    for _ in 0..len {
+        totals.push(counts.iter().sum());
+        counts = matrix.step(&counts);
+    }
+    totals
+}
+
+fn mod_pow(mut base: u64, mut exp: u64, p: u64) -> u64 {
+    let mut result = 1;
+    while exp > 0 {
+        if exp % 2 == 1 {
+            result = result * base % p;
+        }
+        base = base * base % p;
+        exp /= 2;
+    }
+    result
+}
+
+// Primes below 2^32, biggest first, so products of two still fit a u64.
+fn primes() -> impl Iterator<Item = u64> {
+    (2..1u64 << 32)
+        .rev()
+        .filter(|&n| (2..).take_while(|d| d * d <= n).all(|d| n % d != 0))
+}
+
+// Berlekamp-Massey: the shortest c with terms[k] = c[0] * terms[k - 1] +
+// c[1] * terms[k - 2] + ... for every k, working mod p.
+fn berlekamp_massey(terms: &[u64], p: u64) -> Vec<u64> {
+    // connection polynomials, 1 + c(x) x with the signs flipped
+    let mut current = vec![1];
+    let mut previous = vec![1];
+    let mut length = 0;
+    let mut shift = 1;
+    let mut last_discrepancy = 1;
+    for k in 0..terms.len() {
+        let discrepancy = current
+            .iter()
+            .take(length + 1)
+            .enumerate()
+            .fold(0, |d, (i, &c)| (d + c * terms[k - i]) % p);
+        if discrepancy == 0 {
+            shift += 1;
+            continue;
+        }
+        let scale = discrepancy * mod_pow(last_discrepancy, p - 2, p) % p;
+        let before = current.clone();
+        if current.len() < previous.len() + shift {
+            current.resize(previous.len() + shift, 0);
+        }
+        for (i, &b) in previous.iter().enumerate() {
+            current[i + shift] = (current[i + shift] + p - scale * b % p) % p;
+        }
+        if 2 * length <= k {
+            length = k + 1 - length;
+            previous = before;
+            last_discrepancy = discrepancy;
+            shift = 1;
         } else {
-            let res = blink(i).into_iter().map(|i| self.run(i, n - 1)).sum();
-            self.cache.insert((i, n), res);
-            res
+            shift += 1;
         }
     }
+    current.resize(length + 1, 0);
+    current[1..].iter().map(|&c| (p - c) % p).collect()
+}
+
+// Whether every term after the first few follows the recurrence.
+fn holds(recurrence: &[BigInt], terms: &[BigUint]) -> bool {
+    let terms: Vec<BigInt> = terms.iter().map(|t| BigInt::from(t.clone())).collect();
+    (recurrence.len()..terms.len()).all(|k| {
+        let next: BigInt = recurrence
+            .iter()
+            .enumerate()
+            .map(|(i, c)| c * &terms[k - 1 - i])
+            .sum();
+        next == terms[k]
+    })
+}
+
+// The shortest recurrence the totals follow, in whole numbers.  Blinking is a
+// linear map on `size` numbers, so it's no longer than that, and 2 * `size`
+// terms are enough to pin it down.  It's found mod one prime after another
+// and the results glued together until the whole numbers stop changing and
+// check out against every term.
+fn recurrence(terms: &[BigUint]) -> Vec<BigInt> {
+    let mut modulus = BigUint::one();
+    let mut residues: Vec<BigUint> = Vec::new();
+    let mut lifted: Vec<BigInt> = Vec::new();
+    for p in primes() {
+        let reduced: Vec<u64> = terms
+            .iter()
+            .map(|t| (t % p).to_u64().expect("less than p"))
+            .collect();
+        let found = berlekamp_massey(&reduced, p);
+        // a prime that divides something important can make it look shorter
+        if found.len() < residues.len() {
+            continue;
+        }
+        if found.len() > residues.len() {
+            modulus = BigUint::one();
+            residues = vec![BigUint::zero(); found.len()];
+        }
+        // Chinese remainder: keep each residue mod modulus, and make it c mod p
+        let inverse = mod_pow((&modulus % p).to_u64().expect("less than p"), p - 2, p);
+        for (r, c) in residues.iter_mut().zip(found) {
+            let r_mod_p = (&*r % p).to_u64().expect("less than p");
+            let k = (c + p - r_mod_p) % p * inverse % p;
+            *r += &modulus * k;
+        }
+        modulus *= p;
+        let half = &modulus / 2u32;
+        let next: Vec<BigInt> = residues
+            .iter()
+            .map(|r| {
+                if r > &half {
+                    BigInt::from(r.clone()) - BigInt::from(modulus.clone())
+                } else {
+                    BigInt::from(r.clone())
+                }
+            })
+            .collect();
+        if next == lifted && holds(&lifted, terms) {
+            return lifted;
+        }
+        lifted = next;
+    }
+    unreachable!("there are enough primes below 2^32 for any recurrence")
+}
+
+// x^n mod the recurrence's characteristic polynomial, as the coefficients that
+// write term n in terms of the first ones.  It goes by squaring, so takes
+// log n multiplications rather than n blinks.
+fn power_mod(recurrence: &[BigInt], n: u64) -> Vec<BigInt> {
+    let size = recurrence.len();
+    // x^k for k >= size is recurrence[0] x^(k - 1) + recurrence[1] x^(k - 2) ...
+    let reduce = |mut poly: Vec<BigInt>| {
+        for k in (size..poly.len()).rev() {
+            let top = std::mem::take(&mut poly[k]);
+            if top.is_zero() {
+                continue;
+            }
+            for (i, c) in recurrence.iter().enumerate() {
+                poly[k - 1 - i] += &top * c;
+            }
+        }
+        poly.truncate(size);
+        poly
+    };
+    let mut result = reduce(vec![BigInt::one()]);
+    result.resize(size, BigInt::zero());
+    for bit in (0..u64::BITS - n.leading_zeros()).rev() {
+        let mut square = vec![BigInt::zero(); 2 * size];
+        for (i, a) in result.iter().enumerate().filter(|(_, a)| !a.is_zero()) {
+            for (j, b) in result.iter().enumerate() {
+                square[i + j] += a * b;
+            }
+        }
+        result = reduce(square);
+        if (n >> bit) & 1 == 1 {
+            result.insert(0, BigInt::zero());
+            result = reduce(result);
+        }
+    }
+    result
+}
+
+fn blink_matrix(rules: &Rules, stones: &[Stone], n: u64) -> Result<BigUint, StoneError> {
+    let values = reachable(rules, stones, MATRIX_LIMIT)?;
+    let index: HashMap<Stone, usize> = values.iter().enumerate().map(|(i, &v)| (v, i)).collect();
+    let matrix = Matrix {
+        rows: values
+            .iter()
+            .map(|&stone| Ok(rules.blink(stone)?.iter().map(|next| index[next]).collect()))
+            .collect::<Result<_, StoneError>>()?,
+    };
+    let mut counts = vec![BigUint::zero(); values.len()];
+    for stone in stones {
+        counts[index[stone]] += 1u32;
+    }
+    // not many blinks: just do them
+    let needed = 2 * values.len() + 1;
+    if n < needed as u64 {
+        for _ in 0..n {
+            counts = matrix.step(&counts);
+        }
+        return Ok(counts.into_iter().sum());
+    }
+    let terms = totals(&matrix, &counts, needed);
+    let recurrence = recurrence(&terms);
+    let total: BigInt = power_mod(&recurrence, n)
+        .iter()
+        .zip(&terms)
+        .map(|(a, t)| a * BigInt::from(t.clone()))
+        .sum();
+    Ok(total.to_biguint().expect("a count of stones"))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backend {
+    Frequencies,
+    Memo,
+    Matrix,
+}
+
+fn count_stones(
+    rules: &Rules,
+    stones: &[Stone],
+    n: u64,
+    backend: Backend,
+) -> Result<BigUint, StoneError> {
+    match backend {
+        Backend::Frequencies => Ok(simulate(rules, stones, n)?.values().sum()),
+        Backend::Memo => {
+            let mut oracle = Oracle::new(rules);
+            let mut total = BigUint::zero();
+            for &stone in stones {
+                total += oracle.run(stone, n)?;
+            }
+            Ok(total)
+        }
+        Backend::Matrix => blink_matrix(rules, stones, n),
+    }
+}
+
+fn parse_stones(data: &str) -> Vec<Stone> {
+    data.split_whitespace()
+        .map(|x| x.parse::<Stone>().unwrap())
+        .collect()
 }
 
 // #[time_function]
@@ -76,23 +476,54 @@ impl Oracle {
 // }
 
 #[time_function]
-fn part2(data: &str, n: u64) -> usize {
-    let mut oracle = Oracle::new();
-    let stones: Vec<u64> = data
-        .split_whitespace()
-        .map(|x| x.parse::<u64>().unwrap())
-        .collect();
-    let mut total = 0;
-    for stone in stones {
-        total += oracle.run(stone, n);
-    }
-    total
+fn part2(data: &str, n: u64) -> Result<BigUint, StoneError> {
+    count_stones(
+        &Rules::standard(),
+        &parse_stones(data),
+        n,
+        Backend::Frequencies,
+    )
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     let data = get_daily_input(11, 2024)?;
-    println!("part1: {}", part2(&data, 25));
-    println!("part2: {}", part2(&data, 75));
+    println!("part1: {}", part2(&data, 25)?);
+    println!("part2: {}", part2(&data, 75)?);
+
+    // e.g. `--rules zero:1,odd:*3,any:split --blinks 40 --memo` tries a
+    // variant of the rules, and `--matrix` is the way to do a million blinks
+    let args: Vec<String> = std::env::args().collect();
+    let flag = |name: &str| {
+        args.iter()
+            .position(|arg| arg == name)
+            .and_then(|i| args.get(i + 1))
+    };
+    let spec = flag("--rules");
+    let matrix = args.iter().any(|arg| arg == "--matrix");
+    if spec.is_some() || matrix {
+        let rules = match spec {
+            Some(spec) => Rules::parse(spec)?,
+            None => Rules::standard(),
+        };
+        let blinks = match flag("--blinks") {
+            Some(n) => n.parse()?,
+            None => 25,
+        };
+        let backend = if matrix {
+            Backend::Matrix
+        } else if args.iter().any(|arg| arg == "--memo") {
+            Backend::Memo
+        } else {
+            Backend::Frequencies
+        };
+        let names: Vec<&str> = rules.rules.iter().map(|rule| rule.name.as_str()).collect();
+        println!(
+            "{} after {} blinks: {}",
+            names.join(", "),
+            blinks,
+            count_stones(&rules, &parse_stones(&data), blinks, backend)?
+        );
+    }
 
     Ok(())
 }
@@ -104,11 +535,13 @@ mod tests {
 
     #[test]
     fn test_blink() {
-        assert_eq!(blink(0), vec![1]);
-        assert_eq!(blink(1), vec![2024]);
-        assert_eq!(blink(10), vec![1, 0]);
-        assert_eq!(blink(99), vec![9, 9]);
-        assert_eq!(blink(999), vec![2021976]);
+        let rules = Rules::standard();
+        assert_eq!(rules.blink(0), Ok(vec![1]));
+        assert_eq!(rules.blink(1), Ok(vec![2024]));
+        assert_eq!(rules.blink(10), Ok(vec![1, 0]));
+        assert_eq!(rules.blink(99), Ok(vec![9, 9]));
+        assert_eq!(rules.blink(999), Ok(vec![2021976]));
+        assert_eq!(rules.blink(1000), Ok(vec![10, 0]));
     }
 
     // #[test]
@@ -118,6 +551,133 @@ mod tests {
 
     #[test]
     fn test_part2() {
-        assert_eq!(part2(&TESTDATA, 25), 55312);
+        assert_eq!(part2(&TESTDATA, 25).unwrap(), BigUint::from(55312u32));
+    }
+
+    #[test]
+    fn test_backends() {
+        let rules = Rules::standard();
+        let stones = parse_stones(TESTDATA);
+        for n in [0, 1, 6, 25, 40, 75] {
+            let frequencies = count_stones(&rules, &stones, n, Backend::Frequencies);
+            assert_eq!(frequencies, count_stones(&rules, &stones, n, Backend::Memo));
+            assert_eq!(
+                frequencies,
+                count_stones(&rules, &stones, n, Backend::Matrix)
+            );
+        }
+        assert_eq!(
+            count_stones(&rules, &stones, 6, Backend::Matrix),
+            Ok(BigUint::from(22u32))
+        );
+    }
+
+    #[test]
+    fn test_realistic_seed() {
+        // shaped like a real input, which reaches thousands of numbers
+        let rules = Rules::standard();
+        let stones = parse_stones("0 7 6618216 26481 885 42 202642 8791");
+        let values = reachable(&rules, &stones, MATRIX_LIMIT).unwrap();
+        assert_eq!(values.len(), 3878);
+        let frequencies = count_stones(&rules, &stones, 75, Backend::Frequencies);
+        assert_eq!(
+            count_stones(&rules, &stones, 75, Backend::Matrix),
+            frequencies
+        );
+        assert_eq!(frequencies, Ok(BigUint::from(252442982856820u64)));
+    }
+
+    #[test]
+    fn test_variants() {
+        // no rule for odd numbers, so they stay put
+        let rules = Rules::parse("even:+1").unwrap();
+        assert_eq!(rules.blink(4), Ok(vec![5]));
+        assert_eq!(rules.blink(5), Ok(vec![5]));
+        // earlier rules win
+        let rules = Rules::parse("any:7, zero:1").unwrap();
+        assert_eq!(rules.blink(0), Ok(vec![7]));
+        let rules = Rules::parse("odd-digits:*11,any:split").unwrap();
+        assert_eq!(
+            simulate(&rules, &[5], 2),
+            Ok(HashMap::from([(5, BigUint::from(2u32))]))
+        );
+        assert!(Rules::parse("zero").is_err());
+        assert!(Rules::parse("prime:1").is_err());
+        assert!(Rules::parse("zero:*x").is_err());
+    }
+
+    #[test]
+    fn test_deep() {
+        let rules = Rules::standard();
+        let stones = parse_stones(TESTDATA);
+        let deep = count_stones(&rules, &stones, 300, Backend::Frequencies).unwrap();
+        assert!(deep.bits() > 128);
+        assert_eq!(
+            count_stones(&rules, &stones, 300, Backend::Matrix),
+            Ok(deep)
+        );
+        assert_eq!(
+            count_stones(&rules, &stones, 300, Backend::Memo),
+            count_stones(&rules, &stones, 300, Backend::Matrix)
+        );
+    }
+
+    #[test]
+    fn test_recurrence() {
+        let fibonacci = [0, 1, 1, 2, 3, 5, 8, 13, 21, 34];
+        assert_eq!(berlekamp_massey(&fibonacci, 101), vec![1, 1]);
+        let terms: Vec<BigUint> = fibonacci.iter().map(|&t| BigUint::from(t)).collect();
+        let recurrence = recurrence(&terms);
+        assert_eq!(recurrence, vec![BigInt::one(), BigInt::one()]);
+        // x^90 is fib(89) + fib(90) x, mod x^2 - x - 1
+        assert_eq!(
+            power_mod(&recurrence, 90),
+            vec![
+                BigInt::from(1779979416004714189u64),
+                BigInt::from(2880067194370816120u64)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_many_blinks() {
+        // 1 -> 10 -> 1 0, and 0 stays 0, so there's one more stone every
+        // other blink
+        let rules = Rules::parse("zero:0,even-digits:split,any:10").unwrap();
+        assert_eq!(
+            count_stones(&rules, &[1], 1_000_000_000_000, Backend::Matrix),
+            Ok(BigUint::from(500_000_000_001u64))
+        );
+        // 0 -> 1 -> 10 -> 1 0, so blink n has as many as blinks n - 2 and
+        // n - 3 put together
+        let rules = Rules::parse("zero:1,even-digits:split,any:10").unwrap();
+        let count = |n| count_stones(&rules, &[0], n, Backend::Matrix).unwrap();
+        assert_eq!(
+            Ok(count(200)),
+            count_stones(&rules, &[0], 200, Backend::Frequencies)
+        );
+        let n = 1_000_000;
+        assert!(count(n).bits() > 400_000);
+        assert_eq!(count(n), count(n - 2) + count(n - 3));
+    }
+
+    #[test]
+    fn test_overflow() {
+        let rules = Rules::parse("any:*1000000000000").unwrap();
+        assert_eq!(
+            count_stones(&rules, &[7], 3, Backend::Frequencies),
+            Ok(BigUint::one())
+        );
+        let overflow = Err(StoneError::Overflow {
+            stone: 7_000_000_000_000_000_000_000_000_000_000_000_000,
+            rule: "any:*1000000000000".to_string(),
+        });
+        assert_eq!(
+            count_stones(&rules, &[7], 4, Backend::Frequencies),
+            overflow
+        );
+        assert_eq!(count_stones(&rules, &[7], 4, Backend::Memo), overflow);
+        // it never stops growing, so there's no matrix to build
+        assert!(count_stones(&rules, &[7], 1, Backend::Matrix).is_err());
     }
 }
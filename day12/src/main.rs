@@ -1,114 +1,504 @@
-use aochelpers::{get_daily_input, parse_number_grid, Coordinate};
+use aochelpers::get_daily_input;
 use code_timing_macros::time_function;
 //use rayon::prelude::*;
-use std::collections::HashSet;
+use std::collections::{HashSet, VecDeque};
 use std::error::Error;
 
-const DIRECTIONS: [Coordinate<i32>; 4] = [
-    Coordinate { x: 1, y: 0 },
-    Coordinate { x: 0, y: 1 },
-    Coordinate { x: -1, y: 0 },
-    Coordinate { x: 0, y: -1 },
-];
+const DIRECTIONS: [(i32, i32); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
 
-#[time_function]
-fn part1(data: &str) -> u32 {
-    let grid = parse_number_grid::<i32, char>(&data);
-    let width = grid.keys().map(|c| c.x).max().unwrap() + 1;
-    let height = grid.keys().map(|c| c.y).max().unwrap() + 1;
-    // start with (0,0).  flood fill the region.  At boundaries, tag them as for new regions!
-    let mut total = 0;
-    let mut visited = HashSet::new();
-    for (coord, crop) in grid.iter() {
-        if visited.contains(coord) {
-            continue;
+// The plots as one dense row-major grid.
+struct Garden {
+    width: usize,
+    height: usize,
+    crops: Vec<char>,
+}
+
+impl Garden {
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            None
+        } else {
+            Some(y as usize * self.width + x as usize)
         }
-        let mut stack = vec![*coord];
-        let mut region = HashSet::new();
-        let mut perimeter = 0;
-        while let Some(coord) = stack.pop() {
-            for dir in DIRECTIONS {
-                if region.contains(&(coord + dir)) {
-                } else if (coord + dir).x < 0
-                    || (coord + dir).x >= width
-                    || (coord + dir).y < 0
-                    || (coord + dir).y >= height
-                    || grid[&(coord + dir)] != *crop
-                    || visited.contains(&(coord + dir))
-                {
-                    // if *crop == 'B' {
-                    //     println!("Found perimeter at {:?}+{:?}", coord, dir);
-                    // }
-                    perimeter += 1;
-                } else {
-                    if !stack.contains(&(coord + dir)) {
-                        stack.push(coord + dir);
+    }
+
+    // Gives every plot the number of its region, numbering regions in the
+    // order their first plot is met reading the map.
+    fn label(&self) -> (Vec<usize>, usize) {
+        const UNLABELLED: usize = usize::MAX;
+        let mut labels = vec![UNLABELLED; self.crops.len()];
+        let mut count = 0;
+        for start in 0..self.crops.len() {
+            if labels[start] != UNLABELLED {
+                continue;
+            }
+            labels[start] = count;
+            let mut queue = VecDeque::from([start]);
+            while let Some(i) = queue.pop_front() {
+                let (x, y) = ((i % self.width) as i32, (i / self.width) as i32);
+                for (dx, dy) in DIRECTIONS {
+                    if let Some(j) = self.index(x + dx, y + dy) {
+                        if labels[j] == UNLABELLED && self.crops[j] == self.crops[start] {
+                            labels[j] = count;
+                            queue.push_back(j);
+                        }
                     }
                 }
             }
-            region.insert(coord.clone());
-            visited.insert(coord.clone());
+            count += 1;
         }
-        let area = region.len() as u32;
-        total += area * perimeter;
-        //println!("Region {} has area {}, perim {}.", *crop, area, perimeter);
+        (labels, count)
     }
-    total
 }
 
-#[time_function]
-fn part2(data: &str) -> u32 {
-    let grid = parse_number_grid::<i32, char>(&data);
-    let width = grid.keys().map(|c| c.x).max().unwrap() + 1;
-    let height = grid.keys().map(|c| c.y).max().unwrap() + 1;
-    // start with (0,0).  flood fill the region.  At boundaries, tag them as for new regions!
-    let mut total = 0;
-    let mut visited = HashSet::new();
-    for (coord, crop) in grid.iter() {
-        if visited.contains(coord) {
+fn parse_garden(data: &str) -> Garden {
+    let rows: Vec<Vec<char>> = data
+        .lines()
+        .map(|line| line.trim().chars().collect())
+        .filter(|row: &Vec<char>| !row.is_empty())
+        .collect();
+    Garden {
+        width: rows.first().map_or(0, |row| row.len()),
+        height: rows.len(),
+        crops: rows.concat(),
+    }
+}
+
+// Inclusive, in plot coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BoundingBox {
+    left: usize,
+    top: usize,
+    right: usize,
+    bottom: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Region {
+    id: usize,
+    crop: char,
+    area: u64,
+    perimeter: u64,
+    sides: u64,
+    bbox: BoundingBox,
+    // pockets of other plots it completely surrounds
+    holes: usize,
+    // the regions inside those pockets
+    enclosed: Vec<usize>,
+}
+
+// Everything about every region, from one labelling pass plus a look at each
+// plot's neighbours.
+fn analyse(garden: &Garden) -> Vec<Region> {
+    let (labels, count) = garden.label();
+    let mut regions: Vec<Region> = (0..count)
+        .map(|id| Region {
+            id,
+            crop: ' ',
+            area: 0,
+            perimeter: 0,
+            sides: 0,
+            bbox: BoundingBox {
+                left: usize::MAX,
+                top: usize::MAX,
+                right: 0,
+                bottom: 0,
+            },
+            holes: 0,
+            enclosed: Vec::new(),
+        })
+        .collect();
+    let same =
+        |i: usize, x: i32, y: i32| garden.index(x, y).is_some_and(|j| labels[j] == labels[i]);
+    for (i, &label) in labels.iter().enumerate() {
+        let (x, y) = ((i % garden.width) as i32, (i / garden.width) as i32);
+        let region = &mut regions[label];
+        region.crop = garden.crops[i];
+        region.area += 1;
+        region.bbox.left = region.bbox.left.min(x as usize);
+        region.bbox.top = region.bbox.top.min(y as usize);
+        region.bbox.right = region.bbox.right.max(x as usize);
+        region.bbox.bottom = region.bbox.bottom.max(y as usize);
+        for (dx, dy) in DIRECTIONS {
+            if !same(i, x + dx, y + dy) {
+                region.perimeter += 1;
+            }
+        }
+        // A region has as many sides as corners: a plot is at an outside
+        // corner if both neighbours round it are foreign, and an inside one if
+        // both are its own but the diagonal between them isn't.
+        for (a, b) in [(0, 1), (1, 2), (2, 3), (3, 0)] {
+            let (ax, ay) = DIRECTIONS[a];
+            let (bx, by) = DIRECTIONS[b];
+            let (sa, sb) = (same(i, x + ax, y + ay), same(i, x + bx, y + by));
+            if !sa && !sb || sa && sb && !same(i, x + ax + bx, y + ay + by) {
+                region.sides += 1;
+            }
+        }
+    }
+    for region in regions.iter_mut() {
+        find_holes(garden, &labels, region);
+    }
+    regions
+}
+
+// Flood fills everything that isn't the region within a box one plot bigger
+// than it all round: whatever can't reach the edge of the box is a hole.
+fn find_holes(garden: &Garden, labels: &[usize], region: &mut Region) {
+    let (left, top) = (region.bbox.left as i32 - 1, region.bbox.top as i32 - 1);
+    let (width, height) = (
+        region.bbox.right as i32 - left + 2,
+        region.bbox.bottom as i32 - top + 2,
+    );
+    let inside = |x: i32, y: i32| -> bool {
+        garden
+            .index(left + x, top + y)
+            .is_some_and(|i| labels[i] == region.id)
+    };
+    let mut seen = vec![false; (width * height) as usize];
+    for start in 0..width * height {
+        let (sx, sy) = (start % width, start / width);
+        if seen[start as usize] || inside(sx, sy) {
             continue;
         }
-        let mut stack = vec![*coord];
-        let mut region = HashSet::new();
-        let mut corners = 0;
-        while let Some(coord) = stack.pop() {
-            for dir in DIRECTIONS {
-                if region.contains(&(coord + dir)) {
-                    // already visited!
-                } else if (coord + dir).x < 0
-                    || (coord + dir).x >= width
-                    || (coord + dir).y < 0
-                    || (coord + dir).y >= height
-                    || grid[&(coord + dir)] != *crop
-                    || visited.contains(&(coord + dir))
-                {
-                    // neighbor isn't this region, don't explore further
-                } else {
-                    // neighbor IS this region, explore it
-                    if !stack.contains(&(coord + dir)) {
-                        stack.push(coord + dir);
-                    }
+        seen[start as usize] = true;
+        let mut queue = VecDeque::from([(sx, sy)]);
+        let mut pocket = Vec::new();
+        let mut escapes = false;
+        while let Some((x, y)) = queue.pop_front() {
+            if x == 0 || y == 0 || x == width - 1 || y == height - 1 {
+                escapes = true;
+            } else {
+                pocket.push(labels[garden.index(left + x, top + y).unwrap()]);
+            }
+            for (dx, dy) in DIRECTIONS {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 0 || ny < 0 || nx >= width || ny >= height {
+                    continue;
+                }
+                let n = (ny * width + nx) as usize;
+                if !seen[n] && !inside(nx, ny) {
+                    seen[n] = true;
+                    queue.push_back((nx, ny));
                 }
             }
-            // look at 4 neighbors, decide if it's a corner
-            for (a, b) in [(0, 1), (1, 2), (2, 3), (3, 0)] {
-                if (grid.get(&(coord + DIRECTIONS[a]))) != Some(crop)
-                    && grid.get(&(coord + DIRECTIONS[b])) != Some(crop)
-                    || (grid.get(&(coord + DIRECTIONS[a]))) == Some(crop)
-                        && grid.get(&(coord + DIRECTIONS[b])) == Some(crop)
-                        && grid.get(&(coord + DIRECTIONS[a] + DIRECTIONS[b])) != Some(crop)
-                {
-                    corners += 1;
+        }
+        if !escapes {
+            region.holes += 1;
+            region.enclosed.extend(pocket);
+        }
+    }
+    region.enclosed.sort();
+    region.enclosed.dedup();
+}
+
+type Vertex = (i32, i32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Orientation {
+    // as drawn, with y going down the page
+    Clockwise,
+    Anticlockwise,
+}
+
+// A closed loop of fence, as the lattice points where it turns.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Polygon {
+    vertices: Vec<Vertex>,
+}
+
+impl Polygon {
+    fn edges(&self) -> impl Iterator<Item = (Vertex, Vertex)> + '_ {
+        self.vertices
+            .iter()
+            .zip(self.vertices.iter().cycle().skip(1))
+            .map(|(&a, &b)| (a, b))
+    }
+
+    // the shoelace formula: positive for clockwise loops
+    fn signed_area(&self) -> i64 {
+        self.edges()
+            .map(|((ax, ay), (bx, by))| ax as i64 * by as i64 - bx as i64 * ay as i64)
+            .sum::<i64>()
+            / 2
+    }
+
+    fn orientation(&self) -> Orientation {
+        if self.signed_area() > 0 {
+            Orientation::Clockwise
+        } else {
+            Orientation::Anticlockwise
+        }
+    }
+
+    fn length(&self) -> u64 {
+        self.edges()
+            .map(|((ax, ay), (bx, by))| ax.abs_diff(bx) as u64 + ay.abs_diff(by) as u64)
+            .sum()
+    }
+
+    // every turn is the same way round
+    fn is_convex(&self) -> bool {
+        let n = self.vertices.len();
+        let turns: Vec<i64> = (0..n)
+            .map(|i| {
+                let (ax, ay) = self.vertices[i];
+                let (bx, by) = self.vertices[(i + 1) % n];
+                let (cx, cy) = self.vertices[(i + 2) % n];
+                (bx - ax) as i64 * (cy - by) as i64 - (by - ay) as i64 * (cx - bx) as i64
+            })
+            .collect();
+        turns.iter().all(|&t| t > 0) || turns.iter().all(|&t| t < 0)
+    }
+
+    fn svg_path(&self) -> String {
+        let points: Vec<String> = self
+            .vertices
+            .iter()
+            .map(|(x, y)| format!("{} {}", x, y))
+            .collect();
+        format!("M {} Z", points.join(" L "))
+    }
+}
+
+// A region's fences: the outer one runs clockwise and each hole's runs
+// anticlockwise, so the region is always on the right.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Boundary {
+    region: usize,
+    outer: Polygon,
+    holes: Vec<Polygon>,
+}
+
+impl Boundary {
+    // holes count against the outer loop, so this is the region's area
+    fn area(&self) -> i64 {
+        self.outer.signed_area() + self.holes.iter().map(Polygon::signed_area).sum::<i64>()
+    }
+
+    fn sides(&self) -> usize {
+        self.outer.vertices.len() + self.holes.iter().map(|h| h.vertices.len()).sum::<usize>()
+    }
+
+    fn is_convex(&self) -> bool {
+        self.holes.is_empty() && self.outer.is_convex()
+    }
+}
+
+// Every plot edge with a foreign plot (or nothing) on the other side is a
+// length of fence, pointed so its own plot is on the right.  Each one leads
+// on to the next round the same corner; where two plots of a region touch
+// only at a corner, the fence turns left, which keeps pockets on either side
+// of the pinch apart just as find_holes does.
+fn trace_boundaries(garden: &Garden) -> Vec<Boundary> {
+    // which corner of the plot the fence on each side starts from, in
+    // DIRECTIONS order
+    const STARTS: [Vertex; 4] = [(1, 0), (1, 1), (0, 1), (0, 0)];
+    let (labels, count) = garden.label();
+    let mut fences: Vec<HashSet<(Vertex, usize)>> = vec![HashSet::new(); count];
+    for (i, &label) in labels.iter().enumerate() {
+        let (x, y) = ((i % garden.width) as i32, (i / garden.width) as i32);
+        for (k, (dx, dy)) in DIRECTIONS.into_iter().enumerate() {
+            if garden
+                .index(x + dx, y + dy)
+                .is_some_and(|j| labels[j] == label)
+            {
+                continue;
+            }
+            // the fence runs a quarter turn on from the way the neighbour is
+            let (sx, sy) = STARTS[k];
+            fences[label].insert(((x + sx, y + sy), (k + 1) % 4));
+        }
+    }
+    fences
+        .into_iter()
+        .enumerate()
+        .map(|(region, fence)| {
+            let mut loops: Vec<Polygon> = Vec::new();
+            let mut done: HashSet<(Vertex, usize)> = HashSet::new();
+            let mut starts: Vec<&(Vertex, usize)> = fence.iter().collect();
+            starts.sort();
+            for &start in starts {
+                if done.contains(&start) {
+                    continue;
                 }
+                let mut vertices = Vec::new();
+                let (mut at, mut dir) = start;
+                loop {
+                    done.insert((at, dir));
+                    let (dx, dy) = DIRECTIONS[dir];
+                    at = (at.0 + dx, at.1 + dy);
+                    let next = [(dir + 3) % 4, dir, (dir + 1) % 4]
+                        .into_iter()
+                        .find(|&d| fence.contains(&(at, d)))
+                        .expect("fences join up");
+                    if next != dir {
+                        vertices.push(at);
+                    }
+                    dir = next;
+                    if (at, dir) == start {
+                        break;
+                    }
+                }
+                loops.push(Polygon { vertices });
+            }
+            let outer = loops
+                .iter()
+                .position(|l| l.orientation() == Orientation::Clockwise)
+                .expect("a region has an outside");
+            let outer = loops.swap_remove(outer);
+            Boundary {
+                region,
+                outer,
+                holes: loops,
             }
-            region.insert(coord.clone());
-            visited.insert(coord.clone());
+        })
+        .collect()
+}
+
+// Text safe to put inside an XML element or attribute.
+fn xml_escape(text: &str) -> String {
+    let mut out = String::new();
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            c => out.push(c),
         }
-        let area = region.len() as u32;
-        total += area * corners;
-        //println!("Region {} has area {}, perim {}.", *crop, area, perimeter);
     }
-    total
+    out
+}
+
+// The map drawn one unit per plot, each region filled in a colour for its
+// crop with its holes left empty.
+fn to_svg(garden: &Garden, regions: &[Region], boundaries: &[Boundary]) -> String {
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {} {}\">",
+        garden.width, garden.height
+    );
+    for boundary in boundaries {
+        let crop = regions[boundary.region].crop;
+        let path: Vec<String> = std::iter::once(&boundary.outer)
+            .chain(&boundary.holes)
+            .map(Polygon::svg_path)
+            .collect();
+        svg.push_str(&format!(
+            "\n<path d=\"{}\" fill=\"hsl({}, 60%, 70%)\" fill-rule=\"evenodd\" \
+             stroke=\"black\" stroke-width=\"0.05\"><title>{} {}</title></path>",
+            path.join(" "),
+            crop as u32 * 47 % 360,
+            xml_escape(&crop.to_string()),
+            boundary.region
+        ));
+    }
+    svg.push_str("\n</svg>");
+    svg
+}
+
+// What fencing a region costs.
+fn fence_price(region: &Region) -> u64 {
+    region.area * region.perimeter
+}
+
+// With the bulk discount, each straight side costs the same however long.
+fn bulk_price(region: &Region) -> u64 {
+    region.area * region.sides
+}
+
+fn total_price(regions: &[Region], pricing: fn(&Region) -> u64) -> u64 {
+    regions.iter().map(pricing).sum()
+}
+
+// A CSV field, quoted (with any quotes doubled) when it holds a comma, a
+// quote or a line break, as RFC 4180 has it.
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+const CSV_HEADER: &str = "id,crop,area,perimeter,sides,left,top,right,bottom,holes,enclosed";
+
+fn to_csv(regions: &[Region]) -> String {
+    let mut csv = String::from(CSV_HEADER);
+    for r in regions {
+        let enclosed: Vec<String> = r.enclosed.iter().map(|id| id.to_string()).collect();
+        csv.push_str(&format!(
+            "\n{},{},{},{},{},{},{},{},{},{},{}",
+            r.id,
+            csv_field(&r.crop.to_string()),
+            r.area,
+            r.perimeter,
+            r.sides,
+            r.bbox.left,
+            r.bbox.top,
+            r.bbox.right,
+            r.bbox.bottom,
+            r.holes,
+            enclosed.join(";")
+        ));
+    }
+    csv
+}
+
+// A JSON string literal.  Crops are single characters off the map, but a
+// stray quote, backslash or control character still has to be escaped.
+fn json_string(text: &str) -> String {
+    let mut out = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn to_json(regions: &[Region]) -> String {
+    let records: Vec<String> = regions
+        .iter()
+        .map(|r| {
+            let enclosed: Vec<String> = r.enclosed.iter().map(|id| id.to_string()).collect();
+            format!(
+                "{{\"id\":{},\"crop\":{},\"area\":{},\"perimeter\":{},\"sides\":{},\
+                 \"bbox\":{{\"left\":{},\"top\":{},\"right\":{},\"bottom\":{}}},\
+                 \"holes\":{},\"enclosed\":[{}]}}",
+                r.id,
+                json_string(&r.crop.to_string()),
+                r.area,
+                r.perimeter,
+                r.sides,
+                r.bbox.left,
+                r.bbox.top,
+                r.bbox.right,
+                r.bbox.bottom,
+                r.holes,
+                enclosed.join(",")
+            )
+        })
+        .collect();
+    format!("[{}]", records.join(","))
+}
+
+#[time_function]
+fn part1(data: &str) -> u64 {
+    total_price(&analyse(&parse_garden(data)), fence_price)
+}
+
+#[time_function]
+fn part2(data: &str) -> u64 {
+    total_price(&analyse(&parse_garden(data)), bulk_price)
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -116,6 +506,44 @@ fn main() -> Result<(), Box<dyn Error>> {
     println!("part1: {}", part1(&data));
     println!("part2: {}", part2(&data));
 
+    // `--csv` or `--json` dumps every region
+    let args: Vec<String> = std::env::args().collect();
+    if args.iter().any(|arg| arg == "--csv") {
+        println!("{}", to_csv(&analyse(&parse_garden(&data))));
+    }
+    if args.iter().any(|arg| arg == "--json") {
+        println!("{}", to_json(&analyse(&parse_garden(&data))));
+    }
+    // `--svg` draws the regions' fences, and `--polygons` measures them
+    if args.iter().any(|arg| arg == "--svg") {
+        let garden = parse_garden(&data);
+        println!(
+            "{}",
+            to_svg(&garden, &analyse(&garden), &trace_boundaries(&garden))
+        );
+    }
+    if args.iter().any(|arg| arg == "--polygons") {
+        let garden = parse_garden(&data);
+        let regions = analyse(&garden);
+        for boundary in trace_boundaries(&garden) {
+            let fence: u64 = std::iter::once(&boundary.outer)
+                .chain(&boundary.holes)
+                .map(Polygon::length)
+                .sum();
+            println!(
+                "{} {}: {} sides, {} holes, area {} (shoelace {}), fence {}{}",
+                boundary.region,
+                regions[boundary.region].crop,
+                boundary.sides(),
+                boundary.holes.len(),
+                regions[boundary.region].area,
+                boundary.area(),
+                fence,
+                if boundary.is_convex() { ", convex" } else { "" }
+            );
+        }
+    }
+
     Ok(())
 }
 
@@ -130,6 +558,17 @@ OXOXO
 OOOOO
 OXOXO
 OOOOO";
+    const ETEST: &str = "EEEEE
+EXXXX
+EEEEE
+EXXXX
+EEEEE";
+    const ABTEST: &str = "AAAAAA
+AAABBA
+AAABBA
+ABBAAA
+ABBAAA
+AAAAAA";
     const TESTDATA: &str = "RRRRIICCFF
 RRRRIICCCF
 VVRRRCCFFF
@@ -160,5 +599,120 @@ MMMISSJEEE";
     #[test]
     fn test_part2() {
         assert_eq!(part2(&TESTDATA), 1206);
+        assert_eq!(part2(SMALLTEST), 80);
+        assert_eq!(part2(ISLANDTEST), 436);
+        assert_eq!(part2(ETEST), 236);
+        assert_eq!(part2(ABTEST), 368);
+    }
+
+    #[test]
+    fn test_regions() {
+        let regions = analyse(&parse_garden(SMALLTEST));
+        assert_eq!(regions.len(), 5);
+        let c = &regions[2];
+        assert_eq!((c.crop, c.area, c.perimeter, c.sides), ('C', 4, 10, 8));
+        assert_eq!(
+            c.bbox,
+            BoundingBox {
+                left: 2,
+                top: 1,
+                right: 3,
+                bottom: 3
+            }
+        );
+        assert!(regions.iter().all(|r| r.holes == 0));
+    }
+
+    #[test]
+    fn test_holes() {
+        let regions = analyse(&parse_garden(ISLANDTEST));
+        assert_eq!(regions[0].holes, 4);
+        assert_eq!(regions[0].enclosed, vec![1, 2, 3, 4]);
+        // the Bs touch at a corner, but the As round them keep them apart
+        let regions = analyse(&parse_garden(ABTEST));
+        assert_eq!(regions[0].holes, 2);
+        assert_eq!(regions[0].enclosed, vec![1, 2]);
+        let regions = analyse(&parse_garden(ETEST));
+        assert_eq!(regions[0].holes, 0);
+    }
+
+    #[test]
+    fn test_export() {
+        let regions = analyse(&parse_garden(ISLANDTEST));
+        let csv = to_csv(&regions);
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some(CSV_HEADER));
+        assert_eq!(lines.next(), Some("0,O,21,36,20,0,0,4,4,4,1;2;3;4"));
+        assert_eq!(lines.next(), Some("1,X,1,4,4,1,1,1,1,0,"));
+        let json = to_json(&regions[1..2]);
+        assert_eq!(
+            json,
+            "[{\"id\":1,\"crop\":\"X\",\"area\":1,\"perimeter\":4,\"sides\":4,\
+             \"bbox\":{\"left\":1,\"top\":1,\"right\":1,\"bottom\":1},\
+             \"holes\":0,\"enclosed\":[]}]"
+        );
+        assert_eq!(json_string("\u{1b}"), "\"\\u001b\"");
+        assert_eq!(json_string("\"\\"), "\"\\\"\\\\\"");
+        let csv = to_csv(&analyse(&parse_garden(",")));
+        assert_eq!(csv.lines().nth(1), Some("0,\",\",1,4,4,0,0,0,0,0,"));
+        assert_eq!(csv_field("\""), "\"\"\"\"");
+        assert_eq!(csv_field("a\nb"), "\"a\nb\"");
+        assert_eq!(csv_field("X"), "X");
+    }
+
+    #[test]
+    fn test_boundaries() {
+        let garden = parse_garden(ISLANDTEST);
+        let boundaries = trace_boundaries(&garden);
+        let o = &boundaries[0];
+        assert_eq!(o.outer.vertices, vec![(5, 0), (5, 5), (0, 5), (0, 0)]);
+        assert_eq!(o.outer.orientation(), Orientation::Clockwise);
+        assert_eq!(o.holes.len(), 4);
+        assert!(o
+            .holes
+            .iter()
+            .all(|h| h.orientation() == Orientation::Anticlockwise && h.signed_area() == -1));
+        assert_eq!(o.area(), 21);
+        assert!(!o.is_convex());
+        assert!(boundaries[1].is_convex());
+
+        let garden = parse_garden(ABTEST);
+        let boundaries = trace_boundaries(&garden);
+        assert_eq!(boundaries[0].holes.len(), 2);
+        assert!(boundaries[0].holes.iter().all(|h| h.vertices.len() == 4));
+    }
+
+    #[test]
+    fn test_boundaries_agree() {
+        for data in [SMALLTEST, ISLANDTEST, ETEST, ABTEST, TESTDATA] {
+            let garden = parse_garden(data);
+            let regions = analyse(&garden);
+            let boundaries = trace_boundaries(&garden);
+            for (region, boundary) in regions.iter().zip(&boundaries) {
+                assert_eq!(boundary.area(), region.area as i64);
+                assert_eq!(boundary.sides(), region.sides as usize);
+                assert_eq!(boundary.holes.len(), region.holes);
+                let fence: u64 = std::iter::once(&boundary.outer)
+                    .chain(&boundary.holes)
+                    .map(Polygon::length)
+                    .sum();
+                assert_eq!(fence, region.perimeter);
+            }
+        }
+    }
+
+    #[test]
+    fn test_svg() {
+        let garden = parse_garden("AB");
+        let svg = to_svg(&garden, &analyse(&garden), &trace_boundaries(&garden));
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 2 1\">"));
+        assert!(svg.contains("d=\"M 1 0 L 1 1 L 0 1 L 0 0 Z\""));
+        assert!(svg.contains("<title>B 1</title>"));
+        let garden = parse_garden("<&");
+        let svg = to_svg(&garden, &analyse(&garden), &trace_boundaries(&garden));
+        assert!(svg.contains("<title>&lt; 0</title>"));
+        assert!(svg.contains("<title>&amp; 1</title>"));
+        assert_eq!(xml_escape("\"'>"), "&quot;&apos;&gt;");
+        assert!(svg.ends_with("</svg>"));
     }
 }
@@ -3,98 +3,84 @@ use code_timing_macros::time_function;
 use nom::{
     branch::alt,
     bytes::complete::tag,
-    character::complete::{multispace0, u32},
+    character::complete::{multispace0, satisfy, u32},
     multi::many1,
     sequence::{delimited, separated_pair},
     IResult,
 };
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
 use std::error::Error;
+use std::fmt;
 
+// Usually buttons A and B, but there can be any number.
 struct Machine {
-    button_a: Coordinate<i64>,
-    button_b: Coordinate<i64>,
+    buttons: Vec<Coordinate<i64>>,
     prize: Coordinate<i64>,
 }
 
-fn coordinate(input: &str) -> IResult<&str, Coordinate<i64>> {
-    let (i, (x, y)) = delimited(
-        tag("X+"),
-        separated_pair(u32, tag(", Y+"), u32),
-        alt((tag("\n"), tag(""))),
-    )(input)?;
-    Ok((
-        i,
-        Coordinate {
-            x: x as i64,
-            y: y as i64,
-        },
-    ))
+// How the claw machines are played: where the prizes really are, what a press
+// of each button costs (one cost per button, in order), and (if anything) how
+// many times a button may be pressed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ClawConfig {
+    prize_offset: i128,
+    costs: Vec<i128>,
+    max_presses: Option<i128>,
 }
 
-fn eq_coordinate(input: &str) -> IResult<&str, Coordinate<i64>> {
-    let (i, (x, y)) = delimited(
-        tag("X="),
-        separated_pair(u32, tag(", Y="), u32),
-        alt((tag("\n"), tag(""))),
-    )(input)?;
-    Ok((
-        i,
-        Coordinate {
-            x: x as i64,
-            y: y as i64,
-        },
-    ))
+impl ClawConfig {
+    fn part1() -> ClawConfig {
+        ClawConfig {
+            prize_offset: 0,
+            costs: vec![3, 1],
+            max_presses: Some(100),
+        }
+    }
+
+    fn part2() -> ClawConfig {
+        ClawConfig {
+            prize_offset: 10000000000000,
+            costs: vec![3, 1],
+            max_presses: None,
+        }
+    }
 }
 
-fn big_eq_coordinate(input: &str) -> IResult<&str, Coordinate<i64>> {
-    let (i, (x, y)) = delimited(
-        tag("X="),
-        separated_pair(u32, tag(", Y="), u32),
-        alt((tag("\n"), tag(""))),
-    )(input)?;
-    Ok((
-        i,
-        Coordinate {
-            x: 10000000000000 + x as i64,
-            y: 10000000000000 + y as i64,
-        },
-    ))
+// `X+94, Y+34` for a button, or `X=8400, Y=5400` for a prize
+fn coordinate<'a>(
+    x: &'static str,
+    y: &'static str,
+) -> impl FnMut(&'a str) -> IResult<&'a str, Coordinate<i64>> {
+    move |input| {
+        let (i, (x, y)) = delimited(
+            tag(x),
+            separated_pair(u32, tag(y), u32),
+            alt((tag("\n"), tag(""))),
+        )(input)?;
+        Ok((
+            i,
+            Coordinate {
+                x: x as i64,
+                y: y as i64,
+            },
+        ))
+    }
 }
 
-fn parse_machine(input: &str) -> IResult<&str, Machine> {
-    let (i, _) = tag("Button A: ")(input)?;
-    let (i, button_a) = coordinate(i)?;
-    let (i, _) = tag("Button B: ")(i)?;
-    let (i, button_b) = coordinate(i)?;
-    let (i, _) = tag("Prize: ")(i)?;
-    let (i, prize) = eq_coordinate(i)?;
-    let (i, _) = multispace0(i)?;
-    Ok((
-        i,
-        Machine {
-            button_a,
-            button_b,
-            prize,
-        },
-    ))
+fn button(input: &str) -> IResult<&str, Coordinate<i64>> {
+    let (i, _) = tag("Button ")(input)?;
+    let (i, _) = satisfy(|c| c.is_ascii_uppercase())(i)?;
+    let (i, _) = tag(": ")(i)?;
+    coordinate("X+", ", Y+")(i)
 }
 
-fn parse_big_machine(input: &str) -> IResult<&str, Machine> {
-    let (i, _) = tag("Button A: ")(input)?;
-    let (i, button_a) = coordinate(i)?;
-    let (i, _) = tag("Button B: ")(i)?;
-    let (i, button_b) = coordinate(i)?;
+fn parse_machine(input: &str) -> IResult<&str, Machine> {
+    let (i, buttons) = many1(button)(input)?;
     let (i, _) = tag("Prize: ")(i)?;
-    let (i, prize) = big_eq_coordinate(i)?;
+    let (i, prize) = coordinate("X=", ", Y=")(i)?;
     let (i, _) = multispace0(i)?;
-    Ok((
-        i,
-        Machine {
-            button_a,
-            button_b,
-            prize,
-        },
-    ))
+    Ok((i, Machine { buttons, prize }))
 }
 
 fn parse_machines(i: &str) -> IResult<&str, Vec<Machine>> {
@@ -102,61 +88,699 @@ fn parse_machines(i: &str) -> IResult<&str, Vec<Machine>> {
     Ok((i, machines))
 }
 
-fn parse_big_machines(i: &str) -> IResult<&str, Vec<Machine>> {
-    let (i, machines) = many1(parse_big_machine)(i)?;
-    Ok((i, machines))
+// Why a machine can't be won, from least to most nearly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Unwinnable {
+    // the config doesn't say what this button (counting from 0) costs
+    NoCost(usize),
+    // the buttons all move along one line, and the prize isn't on it
+    OffLine,
+    // the prize is between the points whole presses reach
+    NotWholePresses,
+    NeedsNegativePresses,
+    TooManyPresses,
+    // some presses that get nowhere cost less than nothing, so there's no
+    // cheapest way
+    Unbounded,
+}
+
+impl fmt::Display for Unwinnable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Unwinnable::NoCost(button) => write!(
+                f,
+                "there's no cost for button {}",
+                char::from_u32('A' as u32 + *button as u32).unwrap_or('?')
+            ),
+            Unwinnable::OffLine => write!(f, "the prize isn't in line with the buttons"),
+            Unwinnable::NotWholePresses => write!(f, "no whole number of presses gets there"),
+            Unwinnable::NeedsNegativePresses => write!(f, "it would take negative presses"),
+            Unwinnable::TooManyPresses => write!(f, "it would take too many presses"),
+            Unwinnable::Unbounded => write!(f, "it only gets cheaper the more you press"),
+        }
+    }
 }
 
-fn solve(machine: &Machine) -> Option<(i64, i64)> {
-    // prize.x = a * button_a.x + b * button_b.x
-    // prize.y = a * button_a.y + b * button_b.y
+impl Error for Unwinnable {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Play {
+    presses: Vec<i128>,
+    cost: i128,
+}
+
+type Vector = (i128, i128);
+
+fn cross(u: Vector, v: Vector) -> i128 {
+    u.0 * v.1 - u.1 * v.0
+}
+
+// a * x + b * y == g == gcd(a, b), with g never negative
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        (a.abs(), a.signum(), 0)
+    } else {
+        let (g, x, y) = extended_gcd(b, a % b);
+        (g, y, x - (a / b) * y)
+    }
+}
+
+fn div_floor(a: i128, b: i128) -> i128 {
+    let q = a / b;
+    if a % b != 0 && (a < 0) != (b < 0) {
+        q - 1
+    } else {
+        q
+    }
+}
+
+fn div_ceil(a: i128, b: i128) -> i128 {
+    -div_floor(-a, b)
+}
+
+// All x with q * x == p mod m, as x == r mod the returned modulus.
+fn congruence(q: i128, p: i128, m: i128) -> Option<(i128, i128)> {
+    let (g, inverse, _) = extended_gcd(q, m);
+    if p % g != 0 {
+        return None;
+    }
+    let m = m / g;
+    Some(((p / g * inverse).rem_euclid(m), m))
+}
+
+// x == r1 mod m1 and x == r2 mod m2, as one congruence
+fn crt((r1, m1): (i128, i128), (r2, m2): (i128, i128)) -> Option<(i128, i128)> {
+    let (g, inverse, _) = extended_gcd(m1, m2);
+    if (r2 - r1) % g != 0 {
+        return None;
+    }
+    let m = m1 / g * m2;
+    Some((
+        (r1 + m1 * ((r2 - r1) / g * inverse % (m2 / g))).rem_euclid(m),
+        m,
+    ))
+}
+
+// The values of t, from low to high with None for no limit, where
+// start + t * step stays at or above `least`.  False if there aren't any.
+fn at_least(
+    range: &mut (Option<i128>, Option<i128>),
+    start: i128,
+    step: i128,
+    least: i128,
+) -> bool {
+    match step.signum() {
+        1 => range.0 = range.0.max(Some(div_ceil(least - start, step))),
+        -1 => {
+            let bound = div_floor(least - start, step);
+            range.1 = Some(range.1.map_or(bound, |high| high.min(bound)));
+        }
+        _ => return start >= least,
+    }
+    match range {
+        (Some(low), Some(high)) => low <= high,
+        _ => true,
+    }
+}
+
+// Every whole solution is presses[i] = starts[i] + t * steps[i] for integer
+// t.  The cost is linear in t, so the cheapest is at one end of the range of t
+// that keeps the press counts in bounds.
+fn cheapest_along(
+    starts: &[i128],
+    steps: &[i128],
+    costs: &[i128],
+    max_presses: Option<i128>,
+) -> Result<Vec<i128>, Unwinnable> {
+    let mut range = (None, None);
+    for (&start, &step) in starts.iter().zip(steps) {
+        if !at_least(&mut range, start, step, 0) {
+            return Err(Unwinnable::NeedsNegativePresses);
+        }
+    }
+    if let Some(max) = max_presses {
+        for (&start, &step) in starts.iter().zip(steps) {
+            // presses <= max is -presses >= -max
+            if !at_least(&mut range, -start, -step, -max) {
+                return Err(Unwinnable::TooManyPresses);
+            }
+        }
+    }
+    let slope: i128 = costs.iter().zip(steps).map(|(c, s)| c * s).sum();
+    let (low, high) = range;
+    let t = if slope > 0 {
+        low.or(high)
+    } else {
+        high.or(low)
+    }
+    .unwrap_or(0);
+    Ok(starts
+        .iter()
+        .zip(steps)
+        .map(|(start, step)| start + t * step)
+        .collect())
+}
+
+// The cheapest a, b with a * ka + b * kb == kp.  Every solution is
+// (a0 + t * kb / g, b0 - t * ka / g).
+fn solve_line(
+    ka: i128,
+    kb: i128,
+    kp: i128,
+    costs: &[i128],
+    max_presses: Option<i128>,
+) -> Result<Vec<i128>, Unwinnable> {
+    if ka == 0 && kb == 0 {
+        return if kp == 0 {
+            Ok(vec![0, 0])
+        } else {
+            Err(Unwinnable::NotWholePresses)
+        };
+    }
+    let (g, x, y) = extended_gcd(ka, kb);
+    if kp % g != 0 {
+        return Err(Unwinnable::NotWholePresses);
+    }
+    let starts = [x * (kp / g), y * (kp / g)];
+    cheapest_along(&starts, &[kb / g, -ka / g], costs, max_presses)
+}
+
+// The two-button machine, which is all the puzzle has.
+fn solve_pair(
+    (xa, ya): Vector,
+    (xb, yb): Vector,
+    (xp, yp): Vector,
+    costs: &[i128],
+    max_presses: Option<i128>,
+) -> Result<Vec<i128>, Unwinnable> {
     // xp = a * xa + b * xb
     // yp = a * ya + b * yb
-    let xp = machine.prize.x;
-    let xa = machine.button_a.x;
-    let xb = machine.button_b.x;
-    let yp = machine.prize.y;
-    let ya = machine.button_a.y;
-    let yb = machine.button_b.y;
     let determinant = xa * yb - xb * ya;
-    if determinant == 0 {
-        return None;
+    if determinant != 0 {
+        // only one way to get there: Cramer's rule
+        let a = yb * xp - yp * xb;
+        let b = yp * xa - ya * xp;
+        if a % determinant != 0 || b % determinant != 0 {
+            return Err(Unwinnable::NotWholePresses);
+        }
+        return cheapest_along(
+            &[a / determinant, b / determinant],
+            &[0, 0],
+            costs,
+            max_presses,
+        );
+    }
+    let (steps, target) = line_steps(&[(xa, ya), (xb, yb)], (xp, yp))?;
+    solve_line(steps[0], steps[1], target, costs, max_presses)
+}
+
+// Buttons that all move the claw along the same line, which the prize had
+// better be on too.  Measured in steps of the smallest vector along it, each
+// button and the prize is a single number.
+fn line_steps(buttons: &[Vector], (xp, yp): Vector) -> Result<(Vec<i128>, i128), Unwinnable> {
+    let Some(&(dx, dy)) = buttons.iter().find(|&&v| v != (0, 0)) else {
+        return if (xp, yp) == (0, 0) {
+            Ok((vec![0; buttons.len()], 0))
+        } else {
+            Err(Unwinnable::OffLine)
+        };
+    };
+    if dx * yp - dy * xp != 0 {
+        return Err(Unwinnable::OffLine);
+    }
+    let g = extended_gcd(dx, dy).0;
+    let (dx, dy) = (dx / g, dy / g);
+    let steps = |(x, y): Vector| if dx != 0 { x / dx } else { y / dy };
+    let target = steps((xp, yp));
+    if target * dx != xp || target * dy != yp {
+        return Err(Unwinnable::NotWholePresses);
+    }
+    Ok((buttons.iter().map(|&v| steps(v)).collect(), target))
+}
+
+// Three buttons, the first two not in line.  For c presses of the third, the
+// other two are fixed by Cramer's rule, and come out whole only for c in
+// one residue class mod something; along that class all three counts are
+// linear, so it's cheapest_along again.
+fn solve_triple(
+    a: Vector,
+    b: Vector,
+    c: Vector,
+    p: Vector,
+    costs: &[i128],
+    max_presses: Option<i128>,
+) -> Result<Vec<i128>, Unwinnable> {
+    let d = cross(a, b);
+    // a presses = (pa - c * qa) / d, b presses = (pb - c * qb) / d
+    let (pa, qa) = (cross(p, b), cross(c, b));
+    let (pb, qb) = (cross(a, p), cross(a, c));
+    let (r, m) = congruence(qa, pa, d.abs())
+        .zip(congruence(qb, pb, d.abs()))
+        .and_then(|(x, y)| crt(x, y))
+        .ok_or(Unwinnable::NotWholePresses)?;
+    cheapest_along(
+        &[(pa - r * qa) / d, (pb - r * qb) / d, r],
+        &[-m * qa / d, -m * qb / d, m],
+        costs,
+        max_presses,
+    )
+}
+
+// How often a button could be pressed before it overshoots the prize, if no
+// button moves back the other way along some axis the button moves along.
+fn press_bound(buttons: &[Vector], button: Vector, p: Vector) -> Option<i128> {
+    [(button.0, p.0, 0), (button.1, p.1, 1)]
+        .into_iter()
+        .filter(|&(step, _, axis)| {
+            let along = |v: &Vector| if axis == 0 { v.0 } else { v.1 };
+            step != 0 && buttons.iter().all(|v| along(v).signum() != -step.signum())
+        })
+        .map(|(step, target, _)| div_floor(target, step).max(-1))
+        .min()
+}
+
+// Three or more buttons.  One more than a pair is solved exactly by
+// solve_triple; beyond that it's an integer program, solved by solve_ilp.
+// Buttons all in line are measured along the line, with a do-nothing button
+// across it to make up a basis.
+fn search(
+    buttons: &[Vector],
+    p: Vector,
+    costs: &[i128],
+    max_presses: Option<i128>,
+) -> Result<Vec<i128>, Unwinnable> {
+    let n = buttons.len();
+    let pair = (0..n)
+        .flat_map(|i| (i + 1..n).map(move |j| (i, j)))
+        .find(|&(i, j)| cross(buttons[i], buttons[j]) != 0);
+    match pair {
+        Some((i, j)) if n == 3 => {
+            let k = 3 - i - j;
+            let core = [i, j, k];
+            let counts = solve_triple(
+                buttons[i],
+                buttons[j],
+                buttons[k],
+                p,
+                &core.map(|k| costs[k]),
+                max_presses,
+            )?;
+            let mut all = vec![0; n];
+            for (&k, &count) in core.iter().zip(&counts) {
+                all[k] = count;
+            }
+            Ok(all)
+        }
+        Some(_) => solve_ilp(buttons, p, costs, max_presses),
+        None => {
+            let (steps, target) = line_steps(buttons, p)?;
+            if steps.iter().all(|&s| s == 0) {
+                return Ok(vec![0; n]);
+            }
+            let flat: Vec<Vector> = steps
+                .iter()
+                .map(|&s| (s, 0))
+                .chain(std::iter::once((0, 1)))
+                .collect();
+            let costs: Vec<i128> = costs.iter().copied().chain(std::iter::once(0)).collect();
+            let mut all = solve_ilp(&flat, (target, 0), &costs, max_presses)?;
+            all.pop();
+            Ok(all)
+        }
+    }
+}
+
+// Two buttons not in line, which between them reach any point in the plane
+// with some (maybe fractional, maybe negative) number of presses each.
+#[derive(Debug, Clone, Copy)]
+struct Basis {
+    first: usize,
+    second: usize,
+    // the absolute cross product of the two
+    det: i128,
+    sign: i128,
+    u: Vector,
+    v: Vector,
+}
+
+impl Basis {
+    fn new(buttons: &[Vector], first: usize, second: usize) -> Option<Basis> {
+        let (u, v) = (buttons[first], buttons[second]);
+        let d = cross(u, v);
+        (d != 0).then_some(Basis {
+            first,
+            second,
+            det: d.abs(),
+            sign: d.signum(),
+            u,
+            v,
+        })
+    }
+
+    // det times the presses of each basis button that move the claw by w
+    fn coordinates(&self, w: Vector) -> Vector {
+        (self.sign * cross(w, self.v), self.sign * cross(self.u, w))
+    }
+
+    // which whole combination of the basis w is off by: w is one exactly
+    // when this is (0, 0)
+    fn residue(&self, w: Vector) -> Vector {
+        let (a, b) = self.coordinates(w);
+        (a.rem_euclid(self.det), b.rem_euclid(self.det))
+    }
+
+    // Every button's presses, given those of the `free` ones, with the basis
+    // making up the rest of the way to p.
+    fn complete(
+        &self,
+        buttons: &[Vector],
+        p: Vector,
+        free: &[usize],
+        presses: &[i128],
+        max_presses: Option<i128>,
+    ) -> Result<Vec<i128>, Unwinnable> {
+        let mut left = p;
+        for (&k, &count) in free.iter().zip(presses) {
+            left = (left.0 - count * buttons[k].0, left.1 - count * buttons[k].1);
+        }
+        let (a, b) = self.coordinates(left);
+        if a % self.det != 0 || b % self.det != 0 {
+            return Err(Unwinnable::NotWholePresses);
+        }
+        let mut all = vec![0; buttons.len()];
+        all[self.first] = a / self.det;
+        all[self.second] = b / self.det;
+        for (&k, &count) in free.iter().zip(presses) {
+            all[k] = count;
+        }
+        if all.iter().any(|&count| count < 0) {
+            Err(Unwinnable::NeedsNegativePresses)
+        } else if max_presses.is_some_and(|max| all.iter().any(|&count| count > max)) {
+            Err(Unwinnable::TooManyPresses)
+        } else {
+            Ok(all)
+        }
     }
-    let a = (yb * xp - yp * xb) / determinant;
-    let b = (yp * xa - ya * xp) / determinant;
-    if xp == a * xa + b * xb && yp == a * ya + b * yb {
-        Some((a, b))
+}
+
+// The cheapest way to the prize if presses needn't be whole.  There's always
+// one with just the two buttons of some basis pressed, and the basis is the
+// one where no other button is cheaper than making its move with the basis
+// buttons.  Along with the basis come those reduced costs (times det, so
+// they're whole): any presses x reaching p cost
+// (det * the basis's cost + sum of reduced[k] * x[k]) / det.
+fn cheapest_basis(
+    buttons: &[Vector],
+    p: Vector,
+    costs: &[i128],
+) -> Result<(Basis, Vec<i128>), Unwinnable> {
+    let n = buttons.len();
+    let mut reachable = false;
+    for (i, j) in (0..n).flat_map(|i| (i + 1..n).map(move |j| (i, j))) {
+        let Some(basis) = Basis::new(buttons, i, j) else {
+            continue;
+        };
+        let (a, b) = basis.coordinates(p);
+        if a < 0 || b < 0 {
+            continue;
+        }
+        reachable = true;
+        let reduced: Vec<i128> = buttons
+            .iter()
+            .zip(costs)
+            .map(|(&w, &c)| {
+                let (x, y) = basis.coordinates(w);
+                basis.det * c - costs[i] * x - costs[j] * y
+            })
+            .collect();
+        if reduced.iter().all(|&r| r >= 0) {
+            return Ok((basis, reduced));
+        }
+    }
+    // Reachable with no cheapest basis means some presses that get nowhere
+    // cost less than nothing.
+    Err(if reachable {
+        Unwinnable::Unbounded
     } else {
-        None
+        Unwinnable::NeedsNegativePresses
+    })
+}
+
+// Gomory's group relaxation: the presses of the free buttons with the least
+// total reduced cost that leave a whole (if perhaps negative) number of
+// presses for the basis.  Only the residue of what's left matters, so it's a
+// shortest path over the at most det residues.  None if no presses do it.
+fn cheapest_remainder(
+    buttons: &[Vector],
+    basis: &Basis,
+    free: &[usize],
+    reduced: &[i128],
+    p: Vector,
+) -> Option<Vec<i128>> {
+    let target = basis.residue(p);
+    // the cheapest way to each residue, and the press that got there
+    let mut best: HashMap<Vector, (i128, Option<(Vector, usize)>)> = HashMap::new();
+    best.insert((0, 0), (0, None));
+    let mut heap = BinaryHeap::from([Reverse((0, (0, 0)))]);
+    while let Some(Reverse((cost, at))) = heap.pop() {
+        if at == target {
+            let mut presses = vec![0; free.len()];
+            let mut at = at;
+            while let Some((before, n)) = best[&at].1 {
+                presses[n] += 1;
+                at = before;
+            }
+            return Some(presses);
+        }
+        if cost > best[&at].0 {
+            continue;
+        }
+        for (n, &k) in free.iter().enumerate() {
+            let (dx, dy) = basis.residue(buttons[k]);
+            let next = ((at.0 + dx) % basis.det, (at.1 + dy) % basis.det);
+            let cost = cost + reduced[k];
+            if best.get(&next).is_none_or(|&(lowest, _)| cost < lowest) {
+                best.insert(next, (cost, Some((at, n))));
+                heap.push(Reverse((cost, next)));
+            }
+        }
+    }
+    None
+}
+
+// The largest determinant of any square piece of the buttons' matrix.
+fn largest_determinant(buttons: &[Vector]) -> i128 {
+    let entries = buttons.iter().flat_map(|&(x, y)| [x.abs(), y.abs()]);
+    let pairs = buttons
+        .iter()
+        .enumerate()
+        .flat_map(|(i, &u)| buttons[i + 1..].iter().map(move |&v| cross(u, v).abs()));
+    entries.chain(pairs).max().unwrap_or(0).max(1)
+}
+
+// Every number of presses of the free buttons up to their bounds, keeping
+// the cheapest win, or else the nearest miss.  With reduced costs to go on,
+// presses that can't beat the best so far aren't tried.
+struct Search<'a> {
+    buttons: &'a [Vector],
+    p: Vector,
+    costs: &'a [i128],
+    max_presses: Option<i128>,
+    basis: Basis,
+    free: Vec<usize>,
+    bounds: Vec<i128>,
+    reduced: Option<Vec<i128>>,
+    presses: Vec<i128>,
+    best: Option<(i128, Vec<i128>)>,
+    nearest: Unwinnable,
+}
+
+impl Search<'_> {
+    // `floor` is det times the least that presses so far could cost.  The
+    // last free button makes three with the basis, so solve_triple does it.
+    fn walk(&mut self, depth: usize, floor: i128) {
+        if depth + 1 == self.free.len() {
+            let mut left = self.p;
+            for (&k, &count) in self.free.iter().zip(&self.presses[..depth]) {
+                left = (
+                    left.0 - count * self.buttons[k].0,
+                    left.1 - count * self.buttons[k].1,
+                );
+            }
+            let core = [self.basis.first, self.basis.second, self.free[depth]];
+            let found = solve_triple(
+                self.basis.u,
+                self.basis.v,
+                self.buttons[core[2]],
+                left,
+                &core.map(|k| self.costs[k]),
+                self.max_presses,
+            )
+            .map(|counts| {
+                let mut all = vec![0; self.buttons.len()];
+                for (&k, &count) in self.free.iter().zip(&self.presses[..depth]) {
+                    all[k] = count;
+                }
+                for (&k, &count) in core.iter().zip(&counts) {
+                    all[k] = count;
+                }
+                all
+            });
+            match found {
+                Ok(all) => {
+                    let cost = all.iter().zip(self.costs).map(|(n, c)| n * c).sum();
+                    if self.best.as_ref().is_none_or(|(lowest, _)| cost < *lowest) {
+                        self.best = Some((cost, all));
+                    }
+                }
+                Err(why) => self.nearest = self.nearest.max(why),
+            }
+            return;
+        }
+        for count in 0..=self.bounds[depth] {
+            let floor = match &self.reduced {
+                Some(reduced) => floor + count * reduced[self.free[depth]],
+                None => floor,
+            };
+            if self.reduced.is_some()
+                && self
+                    .best
+                    .as_ref()
+                    .is_some_and(|(lowest, _)| floor >= lowest * self.basis.det)
+            {
+                break;
+            }
+            self.presses[depth] = count;
+            self.walk(depth + 1, floor);
+        }
+        self.presses[depth] = 0;
+    }
+}
+
+// Any number of buttons, two of them not in line: the cheapest whole,
+// non-negative presses reaching p.
+//
+// Every solution is fixed by the presses of the buttons outside the cheapest
+// basis, and costs the basis's fractional cost plus their reduced costs.  The
+// presses with the least reduced cost that leave whole presses for the basis
+// are found as a shortest path; for a prize far enough away (as in part 2)
+// the basis presses that leaves are positive, and that's the answer.  Near
+// the origin they might not be, and then the free buttons are searched,
+// giving up on presses whose reduced cost is already too much.  Cook,
+// Gerards, Schrijver and Tardos showed there's a cheapest whole solution
+// within n times the largest determinant of the fractional one in every
+// coordinate, so with no limit on the presses that's as far as the search
+// needs to go.
+fn solve_ilp(
+    buttons: &[Vector],
+    p: Vector,
+    costs: &[i128],
+    max_presses: Option<i128>,
+) -> Result<Vec<i128>, Unwinnable> {
+    let n = buttons.len();
+    let (basis, reduced) = match cheapest_basis(buttons, p, costs) {
+        Ok((basis, reduced)) => (basis, Some(reduced)),
+        // only max_presses stops it getting ever cheaper, so try everything
+        Err(Unwinnable::Unbounded) if max_presses.is_some() => {
+            let basis = (0..n)
+                .flat_map(|i| (i + 1..n).map(move |j| (i, j)))
+                .find_map(|(i, j)| Basis::new(buttons, i, j))
+                .expect("two buttons not in line");
+            (basis, None)
+        }
+        Err(why) => return Err(why),
+    };
+    let free: Vec<usize> = (0..n)
+        .filter(|&k| k != basis.first && k != basis.second)
+        .collect();
+    let (mut floor, mut nearest) = (0, Unwinnable::OffLine);
+    if let Some(reduced) = &reduced {
+        let presses = cheapest_remainder(buttons, &basis, &free, reduced, p)
+            .ok_or(Unwinnable::NotWholePresses)?;
+        match basis.complete(buttons, p, &free, &presses, max_presses) {
+            Ok(all) => return Ok(all),
+            Err(why) => nearest = why,
+        }
+        let (a, b) = basis.coordinates(p);
+        floor = costs[basis.first] * a + costs[basis.second] * b;
     }
+    let proximity = n as i128 * largest_determinant(buttons);
+    let bounds = free
+        .iter()
+        .map(|&k| {
+            [
+                max_presses,
+                press_bound(buttons, buttons[k], p),
+                max_presses.is_none().then_some(proximity),
+            ]
+            .into_iter()
+            .flatten()
+            .min()
+            .expect("max_presses or proximity")
+        })
+        .collect();
+    let mut search = Search {
+        buttons,
+        p,
+        costs,
+        max_presses,
+        basis,
+        presses: vec![0; free.len()],
+        free,
+        bounds,
+        reduced,
+        best: None,
+        nearest,
+    };
+    search.walk(0, floor);
+    search.best.map(|(_, all)| all).ok_or(search.nearest)
 }
 
-fn cost(play: (i64, i64)) -> i64 {
-    play.0 * 3 + play.1
+// How many presses of each button win the prize most cheaply, or why none
+// do.
+fn solve(machine: &Machine, config: &ClawConfig) -> Result<Play, Unwinnable> {
+    let buttons: Vec<Vector> = machine
+        .buttons
+        .iter()
+        .map(|b| (b.x as i128, b.y as i128))
+        .collect();
+    let prize = (
+        machine.prize.x as i128 + config.prize_offset,
+        machine.prize.y as i128 + config.prize_offset,
+    );
+    if config.costs.len() < buttons.len() {
+        return Err(Unwinnable::NoCost(config.costs.len()));
+    }
+    let costs = &config.costs[..buttons.len()];
+    let presses = match buttons[..] {
+        // a do-nothing second button makes one button a pair
+        [a] => solve_pair(a, (0, 0), prize, &[costs[0], 0], config.max_presses)?[..1].to_vec(),
+        [a, b] => solve_pair(a, b, prize, costs, config.max_presses)?,
+        _ => search(&buttons, prize, costs, config.max_presses)?,
+    };
+    let cost = presses.iter().zip(costs).map(|(n, c)| n * c).sum();
+    Ok(Play { presses, cost })
+}
+
+// the cheapest way to win everything that can be won
+fn total_cost(machines: &[Machine], config: &ClawConfig) -> i128 {
+    machines
+        .iter()
+        .filter_map(|m| solve(m, config).ok())
+        .map(|play| play.cost)
+        .sum()
 }
 
 #[time_function]
-fn part1(data: &str) -> IResult<&str, i64> {
-    let (i, machines) = parse_machines(&data)?;
-    Ok((
-        i,
-        machines
-            .iter()
-            .map(|m| cost(solve(m).unwrap_or((0, 0))))
-            .sum::<i64>(),
-    ))
+fn part1(data: &str) -> IResult<&str, i128> {
+    let (i, machines) = parse_machines(data)?;
+    Ok((i, total_cost(&machines, &ClawConfig::part1())))
 }
 
 #[time_function]
-fn part2(data: &str) -> IResult<&str, i64> {
-    let (i, machines) = parse_big_machines(&data)?;
-    Ok((
-        i,
-        machines
-            .iter()
-            .map(|m| cost(solve(m).unwrap_or((0, 0))))
-            .sum::<i64>(),
-    ))
+fn part2(data: &str) -> IResult<&str, i128> {
+    let (i, machines) = parse_machines(data)?;
+    Ok((i, total_cost(&machines, &ClawConfig::part2())))
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -166,6 +790,36 @@ fn main() -> Result<(), Box<dyn Error>> {
     let (_, p2answer) = part2(&data).map_err(|e| format!("Parsing error: {:?}", e))?;
     println!("part2: {}", p2answer);
 
+    // `--report` says how each machine goes under both sets of rules
+    if std::env::args().any(|arg| arg == "--report") {
+        let (_, machines) = parse_machines(&data).map_err(|e| format!("Parsing error: {:?}", e))?;
+        for (name, config) in [
+            ("part1", ClawConfig::part1()),
+            ("part2", ClawConfig::part2()),
+        ] {
+            println!("{}:", name);
+            for (n, machine) in machines.iter().enumerate() {
+                match solve(machine, &config) {
+                    Ok(play) => {
+                        let presses: Vec<String> = play
+                            .presses
+                            .iter()
+                            .zip('A'..)
+                            .map(|(count, button)| format!("{} x{}", button, count))
+                            .collect();
+                        println!(
+                            "  machine {}: {} for {} tokens",
+                            n + 1,
+                            presses.join(", "),
+                            play.cost
+                        )
+                    }
+                    Err(why) => println!("  machine {}: {}", n + 1, why),
+                }
+            }
+        }
+    }
+
     Ok(())
 }
 
@@ -188,34 +842,318 @@ Button B: X+27, Y+71
 Prize: X=18641, Y=10279";
     use super::*;
 
+    fn machine(buttons: &[(i64, i64)], (x, y): (i64, i64)) -> Machine {
+        Machine {
+            buttons: buttons.iter().map(|&(x, y)| Coordinate { x, y }).collect(),
+            prize: Coordinate { x, y },
+        }
+    }
+
+    fn no_limit() -> ClawConfig {
+        ClawConfig {
+            max_presses: None,
+            ..ClawConfig::part1()
+        }
+    }
+
     #[test]
     fn test_solve() {
-        let machine = Machine {
-            button_a: Coordinate { x: 94, y: 34 },
-            button_b: Coordinate { x: 22, y: 67 },
-            prize: Coordinate { x: 8400, y: 5400 },
+        let (part1, part2) = (ClawConfig::part1(), ClawConfig::part2());
+        let m = machine(&[(94, 34), (22, 67)], (8400, 5400));
+        assert_eq!(
+            solve(&m, &part1),
+            Ok(Play {
+                presses: vec![80, 40],
+                cost: 280
+            })
+        );
+        let m = machine(&[(26, 66), (67, 21)], (12748, 12176));
+        assert_eq!(solve(&m, &part1), Err(Unwinnable::NotWholePresses));
+        assert!(solve(&m, &part2).is_ok());
+
+        let m = machine(&[(17, 86), (84, 37)], (7870, 6450));
+        assert_eq!(solve(&m, &part1).map(|p| p.presses), Ok(vec![38, 86]));
+
+        let m = machine(&[(69, 23), (27, 71)], (18641, 10279));
+        assert_eq!(solve(&m, &part1), Err(Unwinnable::NotWholePresses));
+
+        // too big for i64 along the way
+        let m = machine(
+            &[(94, 34), (22, 67)],
+            (1_160_000_000_000_000_000, 1_010_000_000_000_000_000),
+        );
+        assert_eq!(
+            solve(&m, &no_limit()).map(|p| p.presses),
+            Ok(vec![10_000_000_000_000_000, 10_000_000_000_000_000])
+        );
+        assert_eq!(solve(&m, &part1), Err(Unwinnable::TooManyPresses));
+
+        // the only way there is backwards
+        let m = machine(&[(1, 0), (0, 1)], (-1, 2));
+        assert_eq!(
+            solve(&m, &no_limit()),
+            Err(Unwinnable::NeedsNegativePresses)
+        );
+    }
+
+    #[test]
+    fn test_solve_collinear() {
+        let presses = |buttons: &[(i64, i64)], prize| {
+            solve(&machine(buttons, prize), &no_limit()).map(|p| p.presses)
         };
-        assert_eq!(solve(&machine), Some((80, 40)));
-        let machine = Machine {
-            button_a: Coordinate { x: 26, y: 66 },
-            button_b: Coordinate { x: 67, y: 21 },
-            prize: Coordinate { x: 12748, y: 12176 },
+        // B goes twice as far for a third of the price
+        assert_eq!(presses(&[(1, 1), (2, 2)], (10, 10)), Ok(vec![0, 5]));
+        assert_eq!(presses(&[(1, 1), (2, 2)], (11, 11)), Ok(vec![1, 5]));
+        // A goes three times as far for the same price per step
+        assert_eq!(
+            solve(&machine(&[(3, 6), (1, 2)], (7, 14)), &no_limit()).map(|p| p.cost),
+            Ok(7)
+        );
+        assert_eq!(
+            presses(&[(2, 2), (4, 4)], (5, 5)),
+            Err(Unwinnable::NotWholePresses)
+        );
+        assert_eq!(presses(&[(1, 2), (2, 4)], (3, 5)), Err(Unwinnable::OffLine));
+        // opposite ways: a - b == 3
+        assert_eq!(presses(&[(1, 0), (-1, 0)], (3, 0)), Ok(vec![3, 0]));
+        assert_eq!(presses(&[(-1, 0), (1, 0)], (3, 0)), Ok(vec![0, 3]));
+        assert_eq!(presses(&[(0, 0), (0, 3)], (0, 9)), Ok(vec![0, 3]));
+        assert_eq!(presses(&[(0, 0), (0, 0)], (0, 9)), Err(Unwinnable::OffLine));
+        assert_eq!(presses(&[(0, 0), (0, 0)], (0, 0)), Ok(vec![0, 0]));
+        assert_eq!(
+            presses(&[(0, 2), (0, 3)], (0, 1)),
+            Err(Unwinnable::NeedsNegativePresses)
+        );
+        // all B is cheapest, but only a few are allowed
+        let config = ClawConfig {
+            max_presses: Some(4),
+            ..no_limit()
         };
-        assert_eq!(solve(&machine), None);
+        let m = machine(&[(1, 1), (2, 2)], (10, 10));
+        assert_eq!(solve(&m, &config).map(|p| p.presses), Ok(vec![2, 4]));
+        let m = machine(&[(1, 1), (2, 2)], (10, 0));
+        assert_eq!(solve(&m, &config), Err(Unwinnable::OffLine));
+        let m = machine(&[(1, 1), (2, 2)], (13, 13));
+        assert_eq!(solve(&m, &config), Err(Unwinnable::TooManyPresses));
+    }
 
-        let machine = Machine {
-            button_a: Coordinate { x: 17, y: 86 },
-            button_b: Coordinate { x: 84, y: 37 },
-            prize: Coordinate { x: 7870, y: 6450 },
+    #[test]
+    fn test_config() {
+        let (_, machines) = parse_machines(TESTDATA).unwrap();
+        assert_eq!(machines.len(), 4);
+        // pricier B presses tip the first machine's cost but not its presses
+        let config = ClawConfig {
+            costs: vec![3, 2],
+            ..ClawConfig::part1()
         };
-        assert_eq!(solve(&machine), Some((38, 86)));
+        assert_eq!(
+            solve(&machines[0], &config),
+            Ok(Play {
+                presses: vec![80, 40],
+                cost: 320
+            })
+        );
+        assert_eq!(total_cost(&machines, &config), 320 + 38 * 3 + 86 * 2);
+        let config = ClawConfig {
+            max_presses: Some(85),
+            ..ClawConfig::part1()
+        };
+        assert_eq!(total_cost(&machines, &config), 280);
+        // a third button needs a cost of its own
+        let m = machine(&[(94, 34), (22, 67), (30, 30)], (8400, 5400));
+        assert_eq!(solve(&m, &ClawConfig::part2()), Err(Unwinnable::NoCost(2)));
+    }
 
-        let machine = Machine {
-            button_a: Coordinate { x: 69, y: 23 },
-            button_b: Coordinate { x: 27, y: 71 },
-            prize: Coordinate { x: 18641, y: 10279 },
+    #[test]
+    fn test_more_buttons() {
+        let data = "Button A: X+3, Y+1
+Button B: X+1, Y+3
+Button C: X+1, Y+1
+Prize: X=8, Y=8
+
+Button A: X+5, Y+0
+Button B: X+0, Y+5
+Prize: X=10, Y=15
+";
+        let (rest, machines) = parse_machines(data).unwrap();
+        assert_eq!(rest, "");
+        assert_eq!(machines[0].buttons.len(), 3);
+        let config = ClawConfig {
+            costs: vec![3, 3, 2],
+            max_presses: None,
+            ..ClawConfig::part1()
+        };
+        // 2A + 2B costs 12, 8C costs 16, A + B + 4C costs 14
+        assert_eq!(
+            solve(&machines[0], &config),
+            Ok(Play {
+                presses: vec![2, 2, 0],
+                cost: 12
+            })
+        );
+        let config = ClawConfig {
+            costs: vec![3, 3, 1],
+            ..config
+        };
+        assert_eq!(solve(&machines[0], &config).map(|p| p.cost), Ok(8));
+        let config = ClawConfig {
+            max_presses: Some(6),
+            ..config
+        };
+        assert_eq!(
+            solve(&machines[0], &config).map(|p| p.presses),
+            Ok(vec![1, 1, 4])
+        );
+        assert_eq!(
+            solve(&machines[1], &config).map(|p| p.presses),
+            Ok(vec![2, 3])
+        );
+
+        // four buttons: nothing moves further than the last, but 9 of it
+        // leaves (2, 2)
+        let m = machine(&[(1, 0), (0, 1), (1, 1), (2, 3)], (20, 29));
+        let config = ClawConfig {
+            costs: vec![1, 1, 1, 1],
+            ..no_limit()
+        };
+        assert_eq!(solve(&m, &config).map(|p| p.presses), Ok(vec![0, 0, 2, 9]));
+        // going both ways along both axes is fine while presses cost something
+        let m = machine(&[(1, 0), (-1, 0), (0, 1), (0, -1)], (3, 4));
+        assert_eq!(solve(&m, &config).map(|p| p.presses), Ok(vec![3, 0, 4, 0]));
+        // but if there and back pays, there's no cheapest way
+        let config = ClawConfig {
+            costs: vec![1, -2, 1, 1],
+            ..config
         };
-        assert_eq!(solve(&machine), None);
+        assert_eq!(solve(&m, &config), Err(Unwinnable::Unbounded));
+        let config = ClawConfig {
+            max_presses: Some(5),
+            ..config
+        };
+        assert_eq!(solve(&m, &config).map(|p| p.presses), Ok(vec![5, 2, 4, 0]));
+        // all in line, and opposite ways
+        let m = machine(&[(1, 0), (-1, 0), (2, 0)], (3, 0));
+        let config = ClawConfig {
+            costs: vec![1, 1, 1],
+            ..no_limit()
+        };
+        assert_eq!(solve(&m, &config).map(|p| p.presses), Ok(vec![1, 0, 1]));
+        let m = machine(&[(2, 0), (4, 0), (6, 0)], (3, 0));
+        assert_eq!(solve(&m, &config), Err(Unwinnable::NotWholePresses));
+        let m = machine(&[(2, 2), (3, 3), (0, 0)], (1, 1));
+        assert_eq!(solve(&m, &config), Err(Unwinnable::NeedsNegativePresses));
+        // a single button
+        let m = machine(&[(2, 3)], (8, 12));
+        assert_eq!(solve(&m, &no_limit()).map(|p| p.presses), Ok(vec![4]));
+    }
+
+    #[test]
+    fn test_triple_matches_search() {
+        // every small prize: the exact three-button answer against trying
+        // every number of presses
+        let buttons = [(4, 1), (1, 3), (2, 2)];
+        let config = ClawConfig {
+            costs: vec![3, 1, 2],
+            max_presses: Some(12),
+            ..ClawConfig::part1()
+        };
+        for x in 0..30 {
+            for y in 0..30 {
+                let mut best = None;
+                for a in 0..=12 {
+                    for b in 0..=12 {
+                        for c in 0..=12 {
+                            let at = (4 * a + b + 2 * c, a + 3 * b + 2 * c);
+                            if at == (x, y) {
+                                let cost = 3 * a + b + 2 * c;
+                                best = Some(best.map_or(cost, |b: i128| b.min(cost)));
+                            }
+                        }
+                    }
+                }
+                let found = solve(&machine(&buttons, (x as i64, y as i64)), &config);
+                assert_eq!(found.ok().map(|p| p.cost), best, "{:?}", (x, y));
+            }
+        }
+    }
+
+    // the cheapest way to every point the buttons reach with at most `most`
+    // presses each
+    fn every_way(buttons: &[Vector], costs: &[i128], most: i128) -> HashMap<Vector, i128> {
+        let mut cheapest = HashMap::from([((0, 0), 0)]);
+        for (&(x, y), &cost) in buttons.iter().zip(costs) {
+            let mut next = HashMap::new();
+            for (&(px, py), &paid) in &cheapest {
+                for n in 0..=most {
+                    let at = (px + n * x, py + n * y);
+                    let paid = paid + n * cost;
+                    let lowest = next.entry(at).or_insert(paid);
+                    *lowest = paid.min(*lowest);
+                }
+            }
+            cheapest = next;
+        }
+        cheapest
+    }
+
+    #[test]
+    fn test_ilp_matches_search() {
+        // with a limit, and a button that goes back
+        let buttons = [(3, 1), (1, 3), (-1, 2), (4, 0)];
+        let config = ClawConfig {
+            costs: vec![3, 1, 2, 2],
+            max_presses: Some(5),
+            ..ClawConfig::part1()
+        };
+        let cheapest = every_way(&buttons, &config.costs, 5);
+        let m = |x, y| machine(&[(3, 1), (1, 3), (-1, 2), (4, 0)], (x, y));
+        for x in -5..30 {
+            for y in 0..30 {
+                let found = solve(&m(x, y), &config).ok().map(|p| p.cost);
+                assert_eq!(found, cheapest.get(&(x as i128, y as i128)).copied());
+            }
+        }
+        // with no limit, where nothing goes further than 20 presses can
+        let buttons = [(3, 1), (1, 3), (2, 2), (5, 4), (1, 1)];
+        let config = ClawConfig {
+            costs: vec![3, 1, 2, 4, 2],
+            ..no_limit()
+        };
+        let cheapest = every_way(&buttons, &config.costs, 20);
+        let m = |x, y| machine(&[(3, 1), (1, 3), (2, 2), (5, 4), (1, 1)], (x, y));
+        for x in 0..20 {
+            for y in 0..20 {
+                let found = solve(&m(x, y), &config).ok().map(|p| p.cost);
+                assert_eq!(found, cheapest.get(&(x as i128, y as i128)).copied());
+            }
+        }
+    }
+
+    #[test]
+    fn test_ilp_far_away() {
+        // A, B, then A + B a token cheaper and 2A a token cheaper
+        let buttons = [(26, 66), (67, 21), (93, 87), (52, 132)];
+        let prize = (12748, 12176);
+        let config = ClawConfig {
+            costs: vec![3, 1, 3, 5],
+            ..ClawConfig::part2()
+        };
+        let pair = solve(&machine(&buttons[..2], prize), &config).unwrap();
+        let (a, b) = (pair.presses[0], pair.presses[1]);
+        assert!(a > 0 && b > 0);
+        // swap as many A + B as possible for the cheaper button, then pairs of
+        // the A left over
+        let both = a.min(b);
+        let play = solve(&machine(&buttons, prize), &config).unwrap();
+        assert_eq!(play.cost, pair.cost - both - (a - both) / 2);
+        let at = buttons
+            .iter()
+            .zip(&play.presses)
+            .fold((0, 0), |(x, y), (v, &n)| {
+                (x + n * v.0 as i128, y + n * v.1 as i128)
+            });
+        assert_eq!(at, (12748 + 10000000000000, 12176 + 10000000000000));
+        assert!(play.presses.iter().all(|&n| n >= 0));
     }
 
     #[test]
@@ -87,28 +87,57 @@ fn part1(robots: &Vec<Robot>, width: i32, height: i32, ticks: i32) -> i32 {
     score(&robots, width, height)
 }
 
-#[time_function]
-fn part2(robots: &Vec<Robot>, width: i32, height: i32) -> usize {
-    // by minimum safety score (max is uninteresting)
-    // (0..(width * height))
-    //     .map(|i| score(&simulate(robots, width, height, i), width, height))
-    //     .enumerate()
-    //     .min_by_key(|(_, score)| *score)
-    //     .map(|(i, _)| i)
-    //     .unwrap()
-    // neat things at 28, 86, 129, 189, 230, 292, 331
-    // so at 28 + 101x and at 86 + 103x
-    //
-    for i in 0..(width * height) {
-        if i < 100 {
-            continue;
-        };
-        if (i - 28) % 101 == 0 && (i - 86) % 103 == 0 {
-            println!("i: {}", i);
-            return i as usize;
-        }
+// How spread out the robots are along one axis after `ticks`: n^2 times the
+// variance of their positions, which keeps it a whole number.
+fn spread(robots: &[Robot], axis: fn(&Robot) -> (i32, i32), size: i32, ticks: i64) -> i64 {
+    let (mut sum, mut squares) = (0, 0);
+    for robot in robots {
+        let (p, v) = axis(robot);
+        let at = (p as i64 + v as i64 * ticks).rem_euclid(size as i64);
+        sum += at;
+        squares += at * at;
+    }
+    robots.len() as i64 * squares - sum * sum
+}
+
+// Along one axis the robots are back where they started every `size` ticks,
+// so the tick in that cycle when they're bunched up tightest.
+fn tightest(robots: &[Robot], axis: fn(&Robot) -> (i32, i32), size: i32) -> i64 {
+    (0..size as i64)
+        .min_by_key(|&ticks| spread(robots, axis, size, ticks))
+        .unwrap()
+}
+
+// a * x + b * y == gcd(a, b)
+fn extended_gcd(a: i64, b: i64) -> (i64, i64, i64) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (g, x, y) = extended_gcd(b, a % b);
+        (g, y, x - (a / b) * y)
+    }
+}
+
+// The t with t == r1 mod m1 and t == r2 mod m2, as t mod lcm(m1, m2).  The
+// moduli needn't be coprime, but then there might not be one.
+fn crt((r1, m1): (i64, i64), (r2, m2): (i64, i64)) -> Option<(i64, i64)> {
+    let (g, inverse, _) = extended_gcd(m1, m2);
+    if (r2 - r1) % g != 0 {
+        return None;
     }
-    return 0;
+    let lcm = m1 / g * m2;
+    let t = r1 + m1 * ((r2 - r1) / g * inverse).rem_euclid(m2 / g);
+    Some((t.rem_euclid(lcm), lcm))
+}
+
+// The picture is the moment the robots are bunched up along both axes at
+// once.  Each axis on its own has its quietest tick somewhere in a cycle of
+// width (or height) ticks, and the two line up once every lcm(width, height).
+#[time_function]
+fn part2(robots: &Vec<Robot>, width: i32, height: i32) -> Option<usize> {
+    let x = tightest(robots, |r| (r.x, r.vx), width);
+    let y = tightest(robots, |r| (r.y, r.vy), height);
+    crt((x, width as i64), (y, height as i64)).map(|(ticks, _)| ticks as usize)
 }
 
 fn print_robots(robots: &Vec<Robot>, width: i32, height: i32) {
@@ -143,9 +172,12 @@ fn main() -> Result<(), Box<dyn Error>> {
     let (i, robots) = parse_robots(&data).map_err(|e| format!("Parsing error: {:?}", e))?;
     assert_eq!(i, "");
     println!("part1: {}", part1(&robots, 101, 103, 100));
-    let p2answer = part2(&robots, 101, 103) as i32;
+    let p2answer = part2(&robots, 101, 103).ok_or("the axes never line up")? as i32;
     println!("part2: {}", p2answer);
-    //print_robots(&simulate(&robots, 101, 103, p2answer), 101, 103);
+    // `--show` draws the picture
+    if std::env::args().any(|arg| arg == "--show") {
+        print_robots(&simulate(&robots, 101, 103, p2answer), 101, 103);
+    }
     // let mut robots = robots.clone();
     // let mut counter = -2902;
     // if counter > 0 {
@@ -189,6 +221,41 @@ p=9,5 v=-3,-3";
     fn test_part2() {
         let (i, robots) = parse_robots(&TESTDATA).unwrap();
         assert_eq!(i, "");
-        assert_eq!(part2(&robots, 11, 7), 0);
+        let ticks = part2(&robots, 11, 7).unwrap();
+        assert!(ticks < 77);
+        assert_eq!(ticks as i64 % 11, tightest(&robots, |r| (r.x, r.vx), 11));
+        assert_eq!(ticks as i64 % 7, tightest(&robots, |r| (r.y, r.vy), 7));
+    }
+
+    // robots that all meet in a little square at `ticks`, and are all over
+    // the place before and after
+    fn meeting(width: i32, height: i32, ticks: i32) -> Vec<Robot> {
+        (0..40)
+            .map(|i| {
+                let (vx, vy) = (i % 9 - 4, i / 9 - 2);
+                Robot {
+                    x: (10 + i % 2 - vx * ticks).rem_euclid(width),
+                    y: (20 + i % 3 - vy * ticks).rem_euclid(height),
+                    vx,
+                    vy,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_picture() {
+        assert_eq!(part2(&meeting(31, 37, 500), 31, 37), Some(500));
+        // a square room, so the two axes have the same cycle
+        assert_eq!(part2(&meeting(29, 29, 11), 29, 29), Some(11));
+    }
+
+    #[test]
+    fn test_crt() {
+        assert_eq!(crt((28, 101), (86, 103)), Some((7502, 10403)));
+        assert_eq!(crt((2, 3), (3, 5)), Some((8, 15)));
+        // 4 and 6 share a factor of 2, so the residues must agree mod 2
+        assert_eq!(crt((1, 4), (3, 6)), Some((9, 12)));
+        assert_eq!(crt((1, 4), (2, 6)), None);
     }
 }
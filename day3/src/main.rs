@@ -3,11 +3,15 @@ use nom::{
     branch::alt,
     bytes::complete::tag,
     character::complete::{anychar, u32},
+    combinator::{map, value},
     multi::fold_many1,
     sequence::{delimited, separated_pair},
+    Err::Incomplete,
     IResult,
 };
 use std::error::Error;
+use std::fs::File;
+use std::io::{self, ErrorKind, Read};
 use std::ops::Add;
 
 fn mul(i: &str) -> IResult<&str, u32> {
@@ -24,6 +28,7 @@ fn part1(i: &str) -> IResult<&str, u32> {
     fold_many1(alt((mul, trash)), || 0, u32::add)(i)
 }
 
+#[derive(Clone, Copy, Debug)]
 enum Instr {
     Mul { v: u32 },
     Do,
@@ -54,7 +59,7 @@ fn trash2(i: &str) -> IResult<&str, Instr> {
 
 #[derive(Clone, Copy, Debug)]
 struct State {
-    total: u32,
+    total: u64,
     on: bool,
 }
 
@@ -64,19 +69,122 @@ fn interpreter(state: State, instr: Instr) -> State {
         Do => State { on: true, ..state },
         Dont => State { on: false, ..state },
         Mul { v } => State {
-            total: state.total + if state.on { v } else { 0 },
+            total: state.total + if state.on { v as u64 } else { 0 },
             ..state
         },
     }
 }
 
-fn part2(i: &str) -> IResult<&str, u32> {
+fn part2(i: &str) -> IResult<&str, u64> {
     let state = State { total: 0, on: true };
     let (i, state) = fold_many1(alt((mul2, do2, dont2, trash2)), || state, interpreter)(i)?;
     Ok((i, state.total))
 }
 
+const CHUNK_SIZE: usize = 64 * 1024;
+
+// Streaming twins of mul2/do2/dont2: they return Incomplete when a token runs
+// off the end of the buffer, which tells the scanner to wait for more input.
+fn stream_instr(i: &[u8]) -> IResult<&[u8], Instr> {
+    use nom::bytes::streaming::tag;
+    use nom::character::streaming::u32;
+    alt((
+        map(
+            delimited(tag("mul("), separated_pair(u32, tag(","), u32), tag(")")),
+            |(a, b)| Mul { v: a * b },
+        ),
+        value(Do, tag("do()")),
+        value(Dont, tag("don't()")),
+    ))(i)
+}
+
+// Scans corrupted memory a chunk at a time.  Only the unfinished tail of a
+// chunk is kept around, so memory use doesn't grow with the input.
+struct Scanner {
+    state: State,
+    conditionals: bool,
+    pending: Vec<u8>,
+}
+
+impl Scanner {
+    fn new(conditionals: bool) -> Self {
+        Scanner {
+            state: State { total: 0, on: true },
+            conditionals,
+            pending: Vec::new(),
+        }
+    }
+
+    fn feed(&mut self, chunk: &[u8]) {
+        self.pending.extend_from_slice(chunk);
+        let consumed = self.scan(false);
+        self.pending.drain(..consumed);
+    }
+
+    fn finish(mut self) -> State {
+        self.scan(true);
+        self.state
+    }
+
+    // Run the interpreter over the pending bytes and return how many were used
+    // up.  A token cut off by the end of the buffer is left for the next chunk,
+    // unless this is the end of the input, in which case it's just trash.
+    fn scan(&mut self, eof: bool) -> usize {
+        let mut i = &self.pending[..];
+        loop {
+            match stream_instr(i) {
+                Ok((rest, instr)) => {
+                    let instr = match instr {
+                        Do | Dont if !self.conditionals => Trash,
+                        _ => instr,
+                    };
+                    self.state = interpreter(self.state, instr);
+                    i = rest;
+                }
+                Err(Incomplete(_)) if !eof => break,
+                Err(_) if i.is_empty() => break,
+                Err(_) => {
+                    // skip straight to the next byte that could start a token
+                    i = &i[1..];
+                    let next = i
+                        .iter()
+                        .position(|&b| b == b'm' || b == b'd')
+                        .unwrap_or(i.len());
+                    i = &i[next..];
+                }
+            }
+        }
+        self.pending.len() - i.len()
+    }
+}
+
+fn scan_reader<R: Read>(mut reader: R, chunk_size: usize, conditionals: bool) -> io::Result<u64> {
+    let mut scanner = Scanner::new(conditionals);
+    let mut chunk = vec![0; chunk_size];
+    loop {
+        match reader.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(n) => scanner.feed(&chunk[..n]),
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(scanner.finish().total)
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
+    // Given a path, stream it instead of loading the puzzle input into memory.
+    if let Some(path) = std::env::args().nth(1) {
+        println!(
+            "part1: {}",
+            scan_reader(File::open(&path)?, CHUNK_SIZE, false)?
+        );
+        println!(
+            "part2: {}",
+            scan_reader(File::open(&path)?, CHUNK_SIZE, true)?
+        );
+        return Ok(());
+    }
     let data = get_daily_input(3, 2024)?;
     let (_, p1answer) = part1(&data).map_err(|e| format!("Parsing error: {:?}", e))?;
     println!("part1: {}", p1answer);
@@ -113,4 +221,22 @@ mod tests {
     fn test_part2() {
         assert_eq!(part2(PART_TWO), Ok(("", 48)));
     }
+
+    #[test]
+    fn test_scan_every_chunk_size() {
+        for size in 1..=PART_TWO.len() {
+            assert_eq!(scan_reader(TESTDATA.as_bytes(), size, false).unwrap(), 161);
+            assert_eq!(scan_reader(PART_TWO.as_bytes(), size, true).unwrap(), 48);
+        }
+    }
+
+    #[test]
+    fn test_scan_split_token() {
+        let mut scanner = Scanner::new(true);
+        scanner.feed(b"xmul(1");
+        scanner.feed(b"1,8)don");
+        scanner.feed(b"'t()mul(2,2)d");
+        scanner.feed(b"o()mul(3,");
+        assert_eq!(scanner.finish().total, 88);
+    }
 }
@@ -1,94 +1,347 @@
-use aochelpers::{get_daily_input, parse_number_grid, Coordinate, Direction::*};
+use aochelpers::{get_daily_input, parse_number_grid, Coordinate, Direction, Direction::*};
 use code_timing_macros::time_function;
 use rayon::prelude::*;
-use std::{collections::HashMap, error::Error};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+};
+
+const ALL_DIRECTIONS: [Direction; 8] = [
+    NorthWest, North, NorthEast, East, SouthEast, South, SouthWest, West,
+];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Match {
+    start: Coordinate<i32>,
+    direction: Direction,
+    word: String,
+}
+
+impl Match {
+    fn cells(&self) -> impl Iterator<Item = Coordinate<i32>> + '_ {
+        let direction = self.direction;
+        std::iter::successors(Some(self.start), move |c| Some(c.neighbour(direction)))
+            .take(self.word.chars().count())
+    }
+}
+
+// Prefix tree over the dictionary, so words sharing a prefix are walked once.
+struct Trie {
+    children: Vec<HashMap<char, usize>>,
+    words: Vec<Option<usize>>,
+}
+
+impl Trie {
+    fn new<S: AsRef<str>>(words: &[S]) -> Self {
+        let mut trie = Trie {
+            children: vec![HashMap::new()],
+            words: vec![None],
+        };
+        for (index, word) in words.iter().enumerate() {
+            let mut node = 0;
+            for c in word.as_ref().chars() {
+                node = match trie.children[node].get(&c) {
+                    Some(&next) => next,
+                    None => {
+                        trie.children.push(HashMap::new());
+                        trie.words.push(None);
+                        let next = trie.children.len() - 1;
+                        trie.children[node].insert(c, next);
+                        next
+                    }
+                };
+            }
+            trie.words[node] = Some(index);
+        }
+        trie
+    }
+}
+
+// Every occurrence of every word, reading in any of the given directions.
+fn word_search<S: AsRef<str> + Sync>(
+    grid: &HashMap<Coordinate<i32>, char>,
+    words: &[S],
+    directions: &[Direction],
+) -> Vec<Match> {
+    let trie = Trie::new(words);
+    grid.par_iter()
+        .flat_map_iter(|(&start, _)| {
+            let mut found = Vec::new();
+            for &direction in directions {
+                let mut pointer = start;
+                let mut node = 0;
+                while let Some(&next) = grid.get(&pointer).and_then(|c| trie.children[node].get(c))
+                {
+                    node = next;
+                    if let Some(index) = trie.words[node] {
+                        found.push(Match {
+                            start,
+                            direction,
+                            word: words[index].as_ref().to_string(),
+                        });
+                    }
+                    pointer = pointer.neighbour(direction);
+                }
+            }
+            found
+        })
+        .collect()
+}
+
+// Draw the grid with every cell that isn't part of a match blanked out.
+fn render_matches(grid: &HashMap<Coordinate<i32>, char>, matches: &[Match]) -> String {
+    let used: HashSet<Coordinate<i32>> = matches.iter().flat_map(|m| m.cells()).collect();
+    let width = grid.keys().map(|c| c.x).max().unwrap_or(-1) + 1;
+    let height = grid.keys().map(|c| c.y).max().unwrap_or(-1) + 1;
+    let mut out = String::new();
+    for y in 0..height {
+        for x in 0..width {
+            let coord = Coordinate { x, y };
+            match grid.get(&coord) {
+                Some(&c) if used.contains(&coord) => out.push(c),
+                _ => out.push('.'),
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
 
 #[time_function]
 fn part1(data: &str) -> u32 {
-    let directions = [
-        NorthWest, North, NorthEast, East, SouthEast, South, SouthWest, West,
-    ];
     let grid = parse_number_grid::<i32, char>(data);
-    let goal = "XMAS".chars().collect::<Vec<char>>();
-    grid.par_iter()
-        .map(|(x, val)| {
-            let mut tot = 0;
-            if *val == goal[0] {
-                for dir in directions {
-                    let mut pointer: Coordinate<i32> = *x;
-                    for i in 1..goal.len() {
-                        pointer = pointer.neighbour(dir);
-                        if grid.get(&pointer) == Some(&goal[i]) {
-                            if i == goal.len() - 1 {
-                                tot += 1;
+    word_search(&grid, &["XMAS"], &ALL_DIRECTIONS).len() as u32
+}
+
+// One cell of a 2D template: `.` matches anything, `[MS]` matches any of the
+// listed characters, and anything else matches itself.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Cell {
+    Any,
+    Is(char),
+    OneOf(Vec<char>),
+}
+
+impl Cell {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Cell::Any => true,
+            Cell::Is(want) => *want == c,
+            Cell::OneOf(want) => want.contains(&c),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Pattern {
+    rows: Vec<Vec<Cell>>,
+}
+
+impl Pattern {
+    fn parse(text: &str) -> Result<Pattern, String> {
+        let mut rows = Vec::new();
+        for line in text.lines() {
+            let mut row = Vec::new();
+            let mut chars = line.chars();
+            while let Some(c) = chars.next() {
+                row.push(match c {
+                    '.' => Cell::Any,
+                    '[' => {
+                        let mut class = Vec::new();
+                        loop {
+                            match chars.next() {
+                                Some(']') => break,
+                                Some(c) => class.push(c),
+                                None => return Err(format!("unterminated class in {:?}", line)),
                             }
-                        } else {
-                            break;
                         }
+                        if class.is_empty() {
+                            return Err(format!("empty class in {:?}", line));
+                        }
+                        class.sort();
+                        class.dedup();
+                        Cell::OneOf(class)
                     }
+                    c => Cell::Is(c),
+                });
+            }
+            rows.push(row);
+        }
+        if rows.is_empty() || rows[0].is_empty() {
+            return Err("empty pattern".to_string());
+        }
+        if rows.iter().any(|row| row.len() != rows[0].len()) {
+            return Err("pattern rows differ in length".to_string());
+        }
+        Ok(Pattern { rows })
+    }
+
+    fn width(&self) -> usize {
+        self.rows[0].len()
+    }
+
+    fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    // quarter turn clockwise
+    fn rotate(&self) -> Pattern {
+        let rows = (0..self.width())
+            .map(|x| {
+                (0..self.height())
+                    .rev()
+                    .map(|y| self.rows[y][x].clone())
+                    .collect()
+            })
+            .collect();
+        Pattern { rows }
+    }
+
+    // mirror left-to-right
+    fn reflect(&self) -> Pattern {
+        let rows = self
+            .rows
+            .iter()
+            .map(|row| row.iter().rev().cloned().collect())
+            .collect();
+        Pattern { rows }
+    }
+
+    // All eight rotations and reflections, without the ones that coincide
+    // because the pattern is symmetric.
+    fn variants(&self) -> Vec<Pattern> {
+        let mut variants: Vec<Pattern> = Vec::new();
+        let mut turned = self.clone();
+        for _ in 0..4 {
+            for candidate in [turned.clone(), turned.reflect()] {
+                if !variants.contains(&candidate) {
+                    variants.push(candidate);
                 }
             }
-            tot
-        })
-        .sum::<u32>()
-}
-
-//rotate a grid expressed as a hashmap from coordinates to chars 90°
-fn rotate_grid(
-    grid: std::collections::HashMap<Coordinate<i32>, char>,
-) -> Box<dyn Iterator<Item = HashMap<Coordinate<i32>, char>> + 'static> {
-    let width = grid.keys().map(|c| c.x).max().unwrap();
-    let height = grid.keys().map(|c| c.y).max().unwrap();
-    Box::new(Box::new(0..4).map(move |rotation| {
-        let mut rotated = HashMap::new();
-        for (coord, char) in grid.iter() {
-            let new_coord = match rotation {
-                0 => coord.clone(),
-                1 => Coordinate {
-                    x: height - coord.y,
-                    y: coord.x,
-                },
-                2 => Coordinate {
-                    x: width - coord.x,
-                    y: height - coord.y,
-                },
-                3 => Coordinate {
-                    x: coord.y,
-                    y: width - coord.x,
-                },
-                _ => panic!("Invalid rotation"),
-            };
-            rotated.insert(new_coord, *char);
+            turned = turned.rotate();
         }
-        rotated
-    }))
+        variants
+    }
 }
 
-#[time_function]
-fn part2(data: &str) -> u32 {
-    let goal = parse_number_grid::<i32, char>("M.M\n.A.\nS.S");
-    let goals: Vec<HashMap<Coordinate<i32>,char>> = rotate_grid(goal).collect();
-    let grid = parse_number_grid::<i32, char>(data);
-    let mut tot = 0;
-    for goal in goals.iter() {      
-        tot += grid.par_iter()
-            .map(|(&grid_coord, _grid_char)| {
-                for (&goal_coord, &goal_char) in goal.iter() {
-                    let target_coord = grid_coord + goal_coord;
-                    if goal_char != '.' && grid.get(&(target_coord)) != Some(&goal_char) {
-                        return 0;
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct PatternMatch {
+    origin: Coordinate<i32>,
+    variant: Pattern,
+}
+
+// Find every placement of any variant of the pattern.  Each distinct pattern
+// row is matched against each grid row once, and a placement is then just a
+// column of row hits.  Variants that pin down the same grid cells (say the
+// rotations of a pattern that only cares about its corners) are reported
+// once.
+fn find_pattern(grid: &[Vec<char>], pattern: &Pattern) -> Vec<PatternMatch> {
+    let variants = pattern.variants();
+    let height = grid.len();
+    let width = grid.iter().map(|row| row.len()).min().unwrap_or(0);
+
+    let mut distinct_rows: Vec<&Vec<Cell>> = Vec::new();
+    let row_ids: Vec<Vec<Option<usize>>> = variants
+        .iter()
+        .map(|variant| {
+            variant
+                .rows
+                .iter()
+                .map(|row| {
+                    if row.iter().all(|cell| *cell == Cell::Any) {
+                        return None;
+                    }
+                    Some(match distinct_rows.iter().position(|r| *r == row) {
+                        Some(id) => id,
+                        None => {
+                            distinct_rows.push(row);
+                            distinct_rows.len() - 1
+                        }
+                    })
+                })
+                .collect()
+        })
+        .collect();
+
+    // row_hits[y][id][x]: does distinct row `id` match grid row `y` starting at `x`?
+    let row_hits: Vec<Vec<Vec<bool>>> = grid
+        .par_iter()
+        .map(|line| {
+            distinct_rows
+                .iter()
+                .map(|row| {
+                    (0..width)
+                        .map(|x| {
+                            x + row.len() <= width
+                                && row.iter().zip(&line[x..]).all(|(cell, &c)| cell.matches(c))
+                        })
+                        .collect()
+                })
+                .collect()
+        })
+        .collect();
+
+    let mut seen = HashSet::new();
+    let mut matches = Vec::new();
+    for (variant, ids) in variants.iter().zip(row_ids.iter()) {
+        if variant.width() > width || variant.height() > height {
+            continue;
+        }
+        let found: Vec<Coordinate<i32>> = (0..=height - variant.height())
+            .into_par_iter()
+            .flat_map_iter(|y| {
+                let row_hits = &row_hits;
+                (0..=width - variant.width())
+                    .filter(move |&x| {
+                        ids.iter()
+                            .enumerate()
+                            .all(|(dy, id)| id.is_none_or(|id| row_hits[y + dy][id][x]))
+                    })
+                    .map(move |x| Coordinate {
+                        x: x as i32,
+                        y: y as i32,
+                    })
+            })
+            .collect();
+        for origin in found {
+            let mut cells: Vec<(i32, i32)> = Vec::new();
+            for (dy, row) in variant.rows.iter().enumerate() {
+                for (dx, cell) in row.iter().enumerate() {
+                    if *cell != Cell::Any {
+                        cells.push((origin.y + dy as i32, origin.x + dx as i32));
                     }
                 }
-                return 1;
-            }).sum::<u32>();
+            }
+            cells.sort();
+            if seen.insert(cells) {
+                matches.push(PatternMatch {
+                    origin,
+                    variant: variant.clone(),
+                });
+            }
+        }
     }
-    tot
+    matches
+}
+
+#[time_function]
+fn part2(data: &str) -> u32 {
+    let grid: Vec<Vec<char>> = data.lines().map(|line| line.chars().collect()).collect();
+    let pattern = Pattern::parse("M.M\n.A.\nS.S").unwrap();
+    find_pattern(&grid, &pattern).len() as u32
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     let data = get_daily_input(4, 2024)?;
     println!("part1: {}", part1(&data));
     println!("part2: {}", part2(&data));
+    if std::env::args().any(|arg| arg == "--render") {
+        let grid = parse_number_grid::<i32, char>(&data);
+        print!(
+            "{}",
+            render_matches(&grid, &word_search(&grid, &["XMAS"], &ALL_DIRECTIONS))
+        );
+    }
 
     Ok(())
 }
@@ -120,6 +373,84 @@ MXMXAXMASX";
         assert_eq!(part1(&TESTDATA), 18);
     }
 
+    #[test]
+    fn test_word_search() {
+        let grid = parse_number_grid::<i32, char>(&TESTDATA);
+        let matches = word_search(&grid, &["XMAS", "SAMX", "MAS"], &[East]);
+        assert_eq!(matches.iter().filter(|m| m.word == "XMAS").count(), 3);
+        assert_eq!(matches.iter().filter(|m| m.word == "SAMX").count(), 2);
+        assert!(matches.contains(&Match {
+            start: Coordinate { x: 5, y: 0 },
+            direction: East,
+            word: "XMAS".to_string(),
+        }));
+        assert!(matches.contains(&Match {
+            start: Coordinate { x: 6, y: 0 },
+            direction: East,
+            word: "MAS".to_string(),
+        }));
+    }
+
+    #[test]
+    fn test_render_matches() {
+        let grid = parse_number_grid::<i32, char>("XMASX\nAAAAM\nAAAAA\nAAAAS");
+        let matches = word_search(&grid, &["XMAS"], &ALL_DIRECTIONS);
+        assert_eq!(matches.len(), 2);
+        assert_eq!(
+            render_matches(&grid, &matches),
+            "XMASX\n....M\n....A\n....S\n"
+        );
+    }
+
+    #[test]
+    fn test_pattern_parse() {
+        let pattern = Pattern::parse("[SM].\n.A").unwrap();
+        assert_eq!(pattern.rows[0][0], Cell::OneOf(vec!['M', 'S']));
+        assert_eq!(pattern.rows[1], vec![Cell::Any, Cell::Is('A')]);
+        assert!(Pattern::parse("[MS").is_err());
+        assert!(Pattern::parse("AB\nA").is_err());
+    }
+
+    #[test]
+    fn test_pattern_variants() {
+        assert_eq!(Pattern::parse("M.M\n.A.\nS.S").unwrap().variants().len(), 4);
+        assert_eq!(Pattern::parse("XM").unwrap().variants().len(), 4);
+        assert_eq!(Pattern::parse("XM\nA.").unwrap().variants().len(), 8);
+        assert_eq!(
+            Pattern::parse("[MS].[MS]\n.A.\n[MS].[MS]")
+                .unwrap()
+                .variants()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_find_pattern_dedup() {
+        let grid: Vec<Vec<char>> = ["MAM", "AAA", "MAM"]
+            .iter()
+            .map(|l| l.chars().collect())
+            .collect();
+        // M.M/... and its rotations all match the same placement
+        let pattern = Pattern::parse("M.M\n...\nM.M").unwrap();
+        assert_eq!(find_pattern(&grid, &pattern).len(), 1);
+        let pattern = Pattern::parse("MA").unwrap();
+        let found = find_pattern(&grid, &pattern);
+        assert_eq!(found.len(), 8);
+        assert!(found.contains(&PatternMatch {
+            origin: Coordinate { x: 1, y: 0 },
+            variant: Pattern::parse("AM").unwrap(),
+        }));
+        // same rectangle, but different cells
+        let grid = vec![vec!['A', 'A']];
+        let found = find_pattern(&grid, &Pattern::parse("A.").unwrap());
+        assert_eq!(found.len(), 2);
+        assert!(found.contains(&PatternMatch {
+            origin: Coordinate { x: 0, y: 0 },
+            variant: Pattern::parse(".A").unwrap(),
+        }));
+    }
+
     #[test]
     fn test_part2() {
         assert_eq!(part2(&TESTDATA), 9);
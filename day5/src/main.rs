@@ -1,66 +1,212 @@
 use aochelpers::get_daily_input;
 use code_timing_macros::time_function;
 // use rayon::prelude::*;
-use std::{cmp::Ordering::*, collections::HashSet, error::Error};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fmt,
+};
 
 fn middle_element(v: &Vec<u32>) -> u32 {
     v[v.len() / 2]
 }
 
-fn is_sorted(book: &Vec<u32>, constraints: &HashSet<(u32, u32)>) -> bool {
-    for (a, b) in constraints {
-        let early = book.iter().position(|&x| x == *a);
-        let late = book.iter().position(|&x| x == *b);
-        match (early, late) {
-            (Some(early), Some(late)) => {
-                if early > late {
-                    return false;
+// rules keyed by their early page, so a book only looks at rules for its own pages
+fn index_rules(constraints: &HashSet<(u32, u32)>) -> HashMap<u32, Vec<u32>> {
+    let mut index: HashMap<u32, Vec<u32>> = HashMap::new();
+    for &(early, late) in constraints {
+        index.entry(early).or_default().push(late);
+    }
+    index
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Violation {
+    early: u32,
+    late: u32,
+    early_pos: usize,
+    late_pos: usize,
+}
+
+fn violations(book: &[u32], index: &HashMap<u32, Vec<u32>>) -> Vec<Violation> {
+    let position: HashMap<u32, usize> = book.iter().enumerate().map(|(i, &p)| (p, i)).collect();
+    let mut found = Vec::new();
+    for (early_pos, &early) in book.iter().enumerate() {
+        for &late in index.get(&early).into_iter().flatten() {
+            if let Some(&late_pos) = position.get(&late) {
+                if late_pos < early_pos {
+                    found.push(Violation {
+                        early,
+                        late,
+                        early_pos,
+                        late_pos,
+                    });
                 }
             }
-            _ => { //pass
-            }
         }
     }
-    true
+    found
+}
+
+// Take `page` out and put it straight after `after`, or at the front.
+#[derive(Debug, PartialEq, Eq)]
+struct Move {
+    page: u32,
+    after: Option<u32>,
+}
+
+// Moves that turn the book into its sorted order, applied in the order given.
+// The pages left alone are a longest run already in sorted order, so this is
+// the fewest moves whenever the sorted order is unambiguous.
+fn fix_moves(book: &[u32], constraints: &HashSet<(u32, u32)>) -> Result<Vec<Move>, OrderError> {
+    let target = order_pages(book, constraints)?.pages;
+    let rank: HashMap<u32, usize> = target.iter().enumerate().map(|(i, &p)| (p, i)).collect();
+
+    // longest increasing subsequence of ranks, by patience sorting
+    let mut tails: Vec<usize> = Vec::new();
+    let mut parent = vec![None; book.len()];
+    for (i, page) in book.iter().enumerate() {
+        let slot = tails.partition_point(|&t| rank[&book[t]] < rank[page]);
+        if slot > 0 {
+            parent[i] = Some(tails[slot - 1]);
+        }
+        if slot == tails.len() {
+            tails.push(i);
+        } else {
+            tails[slot] = i;
+        }
+    }
+    let mut keep = HashSet::new();
+    let mut cursor = tails.last().copied();
+    while let Some(i) = cursor {
+        keep.insert(book[i]);
+        cursor = parent[i];
+    }
+
+    Ok(target
+        .iter()
+        .enumerate()
+        .filter(|(_, page)| !keep.contains(page))
+        .map(|(i, &page)| Move {
+            page,
+            after: i.checked_sub(1).map(|j| target[j]),
+        })
+        .collect())
 }
 
 #[time_function]
 fn part1(data: &str) -> u32 {
     let (constraints, books) = parse_input(data);
+    let index = index_rules(&constraints);
     let mut total = 0;
     for book in books {
-        if is_sorted(&book, &constraints) {
+        if violations(&book, &index).is_empty() {
             total += middle_element(&book);
         }
     }
     total
 }
 
-fn by_constraints(
-    constraints: &HashSet<(u32, u32)>,
-) -> impl FnMut(&u32, &u32) -> std::cmp::Ordering + '_ {
-    move |a, b| {
-        if a == b {
-            Equal
-        } else if constraints.contains(&(*a, *b)) {
-            Less
-        } else {
-            Greater
+#[derive(Debug, PartialEq, Eq)]
+struct PageOrder {
+    pages: Vec<u32>,
+    // more than one ordering satisfies the rules; `pages` keeps unconstrained
+    // pages in their original relative order
+    ambiguous: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum OrderError {
+    // the rules that form the cycle, each one's late page being the next one's early page
+    Cycle(Vec<(u32, u32)>),
+}
+
+impl fmt::Display for OrderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OrderError::Cycle(rules) => {
+                let rules: Vec<String> =
+                    rules.iter().map(|(a, b)| format!("{}|{}", a, b)).collect();
+                write!(
+                    f,
+                    "the page rules go round in a circle: {}",
+                    rules.join(", ")
+                )
+            }
+        }
+    }
+}
+
+impl Error for OrderError {}
+
+// Topologically sort a book using only the rules between its own pages.
+fn order_pages(book: &[u32], constraints: &HashSet<(u32, u32)>) -> Result<PageOrder, OrderError> {
+    let n = book.len();
+    let mut successors = vec![Vec::new(); n];
+    let mut predecessors = vec![Vec::new(); n];
+    for i in 0..n {
+        for j in 0..n {
+            if constraints.contains(&(book[i], book[j])) {
+                successors[i].push(j);
+                predecessors[j].push(i);
+            }
+        }
+    }
+
+    let mut in_degree: Vec<usize> = predecessors.iter().map(|p| p.len()).collect();
+    let mut ready: Vec<usize> = (0..n).filter(|&i| in_degree[i] == 0).collect();
+    let mut pages = Vec::with_capacity(n);
+    let mut ambiguous = false;
+    while !ready.is_empty() {
+        ambiguous |= ready.len() > 1;
+        let (slot, _) = ready.iter().enumerate().min_by_key(|(_, &i)| i).unwrap();
+        let next = ready.swap_remove(slot);
+        pages.push(book[next]);
+        for &succ in &successors[next] {
+            in_degree[succ] -= 1;
+            if in_degree[succ] == 0 {
+                ready.push(succ);
+            }
+        }
+    }
+
+    if pages.len() < n {
+        // Every page left over still has a left-over predecessor, so walking
+        // backwards from any of them must eventually revisit a page.
+        let mut walk = vec![(0..n).find(|&i| in_degree[i] > 0).unwrap()];
+        loop {
+            let current = *walk.last().unwrap();
+            let prev = *predecessors[current]
+                .iter()
+                .find(|&&p| in_degree[p] > 0)
+                .unwrap();
+            if let Some(start) = walk.iter().position(|&i| i == prev) {
+                let mut cycle: Vec<usize> = walk[start..].to_vec();
+                cycle.reverse();
+                let rules = (0..cycle.len())
+                    .map(|k| (book[cycle[k]], book[cycle[(k + 1) % cycle.len()]]))
+                    .collect();
+                return Err(OrderError::Cycle(rules));
+            }
+            walk.push(prev);
         }
     }
+
+    Ok(PageOrder { pages, ambiguous })
 }
 
 #[time_function]
-fn part2(data: &str) -> u32 {
+fn part2(data: &str) -> Result<u32, OrderError> {
     let (constraints, books) = parse_input(data);
+    let index = index_rules(&constraints);
     let mut total = 0;
-    for mut book in books {
-        if !is_sorted(&book, &constraints) {
-            book.sort_by(by_constraints(&constraints));
-            total += middle_element(&book);
+    for book in books {
+        if !violations(&book, &index).is_empty() {
+            let order = order_pages(&book, &constraints)?;
+            total += middle_element(&order.pages);
         }
     }
-    total
+    Ok(total)
 }
 
 fn parse_input(data: &str) -> (HashSet<(u32, u32)>, Vec<Vec<u32>>) {
@@ -83,10 +229,43 @@ fn parse_input(data: &str) -> (HashSet<(u32, u32)>, Vec<Vec<u32>>) {
     (constraint_map, books)
 }
 
+// Say why each rejected book is rejected and how to fix it.
+fn explain(data: &str) {
+    let (constraints, books) = parse_input(data);
+    let index = index_rules(&constraints);
+    for book in books {
+        let broken = violations(&book, &index);
+        if broken.is_empty() {
+            continue;
+        }
+        println!("{:?}", book);
+        for v in broken {
+            println!(
+                "  {} (at {}) must come before {} (at {})",
+                v.early, v.early_pos, v.late, v.late_pos
+            );
+        }
+        match fix_moves(&book, &constraints) {
+            Ok(moves) => {
+                for m in moves {
+                    match m.after {
+                        Some(after) => println!("  move {} after {}", m.page, after),
+                        None => println!("  move {} to the front", m.page),
+                    }
+                }
+            }
+            Err(e) => println!("  cannot be fixed: {}", e),
+        }
+    }
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
     let data = get_daily_input(5, 2024)?;
     println!("part1: {}", part1(&data));
-    println!("part2: {}", part2(&data));
+    println!("part2: {}", part2(&data)?);
+    if std::env::args().any(|arg| arg == "--explain") {
+        explain(&data);
+    }
 
     Ok(())
 }
@@ -123,6 +302,19 @@ mod tests {
 97,13,75,29,47";
     use super::*;
 
+    fn apply_moves(book: &[u32], moves: &[Move]) -> Vec<u32> {
+        let mut book = book.to_vec();
+        for m in moves {
+            book.retain(|&p| p != m.page);
+            let at = match m.after {
+                Some(after) => book.iter().position(|&p| p == after).unwrap() + 1,
+                None => 0,
+            };
+            book.insert(at, m.page);
+        }
+        book
+    }
+
     #[test]
     fn test_parse() {
         let (constraints, books) = parse_input(TESTDATA);
@@ -137,6 +329,67 @@ mod tests {
         assert_eq!(middle_element(&vec![1, 2, 3, 4, 5]), 3);
     }
 
+    #[test]
+    fn test_order_pages() {
+        let (constraints, _) = parse_input(TESTDATA);
+        let order = order_pages(&[97, 13, 75, 29, 47], &constraints).unwrap();
+        assert_eq!(order.pages, vec![97, 75, 47, 29, 13]);
+        assert!(!order.ambiguous);
+        // nothing relates 13 and 75 once 29 and 47 are gone
+        let constraints: HashSet<(u32, u32)> = [(97, 13), (97, 75)].into_iter().collect();
+        let order = order_pages(&[13, 75, 97], &constraints).unwrap();
+        assert_eq!(order.pages, vec![97, 13, 75]);
+        assert!(order.ambiguous);
+    }
+
+    #[test]
+    fn test_order_pages_cycle() {
+        let constraints: HashSet<(u32, u32)> =
+            [(1, 2), (2, 3), (3, 1), (4, 1)].into_iter().collect();
+        match order_pages(&[4, 1, 2, 3], &constraints) {
+            Err(OrderError::Cycle(rules)) => {
+                assert_eq!(rules.len(), 3);
+                for (k, rule) in rules.iter().enumerate() {
+                    assert!(constraints.contains(rule));
+                    assert_eq!(rule.1, rules[(k + 1) % rules.len()].0);
+                }
+            }
+            other => panic!("expected a cycle, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_violations() {
+        let (constraints, _) = parse_input(TESTDATA);
+        let index = index_rules(&constraints);
+        assert!(violations(&[75, 47, 61, 53, 29], &index).is_empty());
+        assert_eq!(
+            violations(&[75, 97, 47, 61, 53], &index),
+            vec![Violation {
+                early: 97,
+                late: 75,
+                early_pos: 1,
+                late_pos: 0
+            }]
+        );
+        assert_eq!(violations(&[97, 13, 75, 29, 47], &index).len(), 4);
+    }
+
+    #[test]
+    fn test_fix_moves() {
+        let (constraints, _) = parse_input(TESTDATA);
+        for (book, fixed, count) in [
+            (vec![75, 97, 47, 61, 53], vec![97, 75, 47, 61, 53], 1),
+            (vec![61, 13, 29], vec![61, 29, 13], 1),
+            (vec![97, 13, 75, 29, 47], vec![97, 75, 47, 29, 13], 2),
+            (vec![75, 29, 13], vec![75, 29, 13], 0),
+        ] {
+            let moves = fix_moves(&book, &constraints).unwrap();
+            assert_eq!(moves.len(), count);
+            assert_eq!(apply_moves(&book, &moves), fixed);
+        }
+    }
+
     #[test]
     fn test_part1() {
         assert_eq!(part1(TESTDATA), 143);
@@ -144,6 +397,11 @@ mod tests {
 
     #[test]
     fn test_part2() {
-        assert_eq!(part2(TESTDATA), 123);
+        assert_eq!(part2(TESTDATA), Ok(123));
+        // 1 before 2 before 3 before 1 can't be put right
+        assert!(matches!(
+            part2("1|2\n2|3\n3|1\n\n3,2,1"),
+            Err(OrderError::Cycle(_))
+        ));
     }
 }
@@ -9,200 +9,369 @@ use std::{
 #[derive(Clone, Debug)]
 struct Map {
     grid: HashMap<Coordinate<i32>, char>,
-    start: Coordinate<i32>,
+    guards: Vec<Guard>,
     width: i32,
     height: i32,
 }
-const START_DIR: Coordinate<i32> = Coordinate { x: 0, y: -1 };
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Guard {
+    start: Coordinate<i32>,
+    // index into STEPS
+    facing: usize,
+}
+
+// North, east, south, west: turning right is the next one along, and the
+// guard markers are in the same order.
+const STEPS: [Coordinate<i32>; 4] = [
+    Coordinate { x: 0, y: -1 },
+    Coordinate { x: 1, y: 0 },
+    Coordinate { x: 0, y: 1 },
+    Coordinate { x: -1, y: 0 },
+];
+const GUARDS: [char; 4] = ['^', '>', 'v', '<'];
 
 fn parse_map(data: &str) -> Map {
     let grid = parse_number_grid::<i32, char>(data);
-    let start = grid
+    let mut guards: Vec<Guard> = grid
         .iter()
-        .find(|(_, &c)| c == '^')
-        .map(|(k, _)| *k)
-        .unwrap();
+        .filter_map(|(&start, c)| {
+            GUARDS
+                .iter()
+                .position(|g| g == c)
+                .map(|facing| Guard { start, facing })
+        })
+        .collect();
+    guards.sort_by_key(|g| (g.start.y, g.start.x));
     let width = grid.keys().map(|c| c.x).max().unwrap() + 1;
     let height = grid.keys().map(|c| c.y).max().unwrap() + 1;
     Map {
         grid,
-        start,
+        guards,
         width,
         height,
     }
 }
 
-fn rotate_dir(dir: Coordinate<i32>) -> Coordinate<i32> {
-    match dir {
-        Coordinate { x: 0, y: -1 } => Coordinate { x: 1, y: 0 },
-        Coordinate { x: 1, y: 0 } => Coordinate { x: 0, y: 1 },
-        Coordinate { x: 0, y: 1 } => Coordinate { x: -1, y: 0 },
-        Coordinate { x: -1, y: 0 } => Coordinate { x: 0, y: -1 },
-        _ => panic!("Invalid direction"),
-    }
-}
-
 #[time_function]
 fn part1(data: &str) -> u32 {
-    let map = parse_map(&data);
-    let visited = tour_without_directions(&map);
+    let map = parse_map(data);
+    let (_, visited) = patrol_all(&map);
     visited.len() as u32
 }
 
-// fn strip_dirs(c: &HashSet<(Coordinate<i32>, Coordinate<i32>)>) -> HashSet<Coordinate<i32>> {
-//     c.iter().map(|&(ref a, _)| a.clone()).collect()
-// }
-
-// fn print_map(
-//     map: &Map,
-//     visited: &HashSet<(Coordinate<i32>, Coordinate<i32>)>,
-//     candidates: &HashSet<Coordinate<i32>>,
-// ) {
-//     let visited = strip_dirs(visited);
-//     for y in 0..map.height {
-//         for x in 0..map.width {
-//             let coord = Coordinate { x, y };
-//             let c = map.grid.get(&coord).unwrap();
-//             if *c == '^' {
-//                 print!("^");
-//                 continue;
-//             }
-//             if candidates.contains(&coord) {
-//                 print!("O");
-//                 continue;
-//             }
-//             if visited.contains(&coord) {
-//                 print!("X");
-//                 continue;
-//             }
-//             print!("{}", c);
-//         }
-//         println!();
-//     }
-// }
-
-fn on_path_to_repeat(map: &Map) -> bool {
-    let mut loc = map.start;
-    let mut dir = START_DIR;
-    let mut visited = HashSet::new();
-
-    loop {
-        if visited.contains(&(loc, dir)) {
-            return true;
+const EXIT: usize = usize::MAX;
+
+// A dense copy of the map plus jump tables: jump[d][c] is where the guard
+// stops walking in direction d from cell c, just short of the next obstacle,
+// or EXIT if she walks off the map.  Directions are indices into STEPS, so
+// turning right is adding one.
+struct Sim {
+    width: i32,
+    height: i32,
+    blocked: Vec<bool>,
+    jump: [Vec<usize>; 4],
+}
+
+impl Sim {
+    fn new(map: &Map) -> Sim {
+        let size = (map.width * map.height) as usize;
+        let mut sim = Sim {
+            width: map.width,
+            height: map.height,
+            blocked: vec![false; size],
+            jump: [
+                vec![EXIT; size],
+                vec![EXIT; size],
+                vec![EXIT; size],
+                vec![EXIT; size],
+            ],
+        };
+        for (&coord, &c) in map.grid.iter() {
+            let cell = sim.index(coord).unwrap();
+            sim.blocked[cell] = c == '#';
         }
-        visited.insert((loc, dir));
-        let next = loc + dir;
-        match map.grid.get(&next) {
-            None => return false,
-            Some(c) if c == &'#' => {
-                while map.grid.get(&(loc + dir)) == Some(&'#') {
-                    dir = rotate_dir(dir);
-                }
-                loc = loc + dir;
+        for (d, &step) in STEPS.iter().enumerate() {
+            // fill each table from the far side, so the cell ahead is already done
+            let mut cells: Vec<usize> = (0..size).collect();
+            if step.x + step.y > 0 {
+                cells.reverse();
             }
-            Some(_) => {
-                loc = next;
+            for cell in cells {
+                sim.jump[d][cell] = match sim.index(sim.coord(cell) + step) {
+                    None => EXIT,
+                    Some(ahead) if sim.blocked[ahead] => cell,
+                    Some(ahead) => sim.jump[d][ahead],
+                };
             }
         }
+        sim
     }
-}
 
-fn on_path_to_repeat_except(map: &Map, except: Coordinate<i32>) -> bool {
-    let mut loc = map.start;
-    let mut dir = START_DIR;
-    let mut visited = HashSet::new();
+    fn index(&self, c: Coordinate<i32>) -> Option<usize> {
+        if c.x >= 0 && c.x < self.width && c.y >= 0 && c.y < self.height {
+            Some((c.y * self.width + c.x) as usize)
+        } else {
+            None
+        }
+    }
 
-    loop {
-        if visited.contains(&(loc, dir)) {
-            return true;
+    fn coord(&self, cell: usize) -> Coordinate<i32> {
+        Coordinate {
+            x: cell as i32 % self.width,
+            y: cell as i32 / self.width,
         }
-        visited.insert((loc, dir));
-        let next = loc + dir;
-        match map.grid.get(&next) {
-            None => return false,
-            Some(c) if c == &'#' || next == except => {
-                while map.grid.get(&(loc + dir)) == Some(&'#') || (loc + dir) == except {
-                    dir = rotate_dir(dir);
+    }
+
+    fn patrol(&self, guard: Guard) -> Patrol {
+        self.patrol_with(guard, None)
+    }
+
+    // Walk a guard cell by cell until she leaves the map or repeats herself,
+    // optionally with one extra obstacle in the way.
+    fn patrol_with(&self, guard: Guard, obstacle: Option<usize>) -> Patrol {
+        let mut cell = self.index(guard.start).unwrap();
+        let mut d = guard.facing;
+        let mut visited = HashSet::new();
+        let mut steps: Vec<(usize, usize)> = Vec::new();
+        let mut seen: HashMap<(usize, usize), usize> = HashMap::new();
+        loop {
+            if let Some(&first) = seen.get(&(cell, d)) {
+                let cycle = steps[first..]
+                    .iter()
+                    .map(|&(cell, d)| (self.coord(cell), STEPS[d]))
+                    .collect();
+                return Patrol {
+                    visited,
+                    outcome: Outcome::Loops { cycle },
+                };
+            }
+            seen.insert((cell, d), steps.len());
+            steps.push((cell, d));
+            visited.insert(self.coord(cell));
+            match self.index(self.coord(cell) + STEPS[d]) {
+                None => {
+                    return Patrol {
+                        visited,
+                        outcome: Outcome::Exits,
+                    }
                 }
-                loc = loc + dir;
+                Some(next) if self.blocked[next] || Some(next) == obstacle => d = (d + 1) % 4,
+                Some(next) => cell = next,
             }
-            Some(_) => {
-                loc = next;
+        }
+    }
+
+    // The unobstructed walk, as (cell, direction, next cell) for the step onto
+    // each cell the first time the guard reaches it.
+    fn first_visits(&self, guard: Guard) -> Vec<(usize, usize, usize)> {
+        let start = self.index(guard.start).unwrap();
+        let mut seen = vec![false; self.blocked.len()];
+        seen[start] = true;
+        let mut steps = Vec::new();
+        let (mut cell, mut d) = (start, guard.facing);
+        let mut turns = HashSet::new();
+        while let Some(next) = self.index(self.coord(cell) + STEPS[d]) {
+            if self.blocked[next] {
+                if !turns.insert((cell, d)) {
+                    // already stuck in a loop, nothing left to reach
+                    break;
+                }
+                d = (d + 1) % 4;
+            } else {
+                if !seen[next] {
+                    seen[next] = true;
+                    steps.push((cell, d, next));
+                }
+                cell = next;
             }
         }
+        steps
     }
-}
 
-// fn print_loop(
-//     map: &Map,
-//     visited: &HashSet<(Coordinate<i32>, Coordinate<i32>)>,
-//     candidate: &Coordinate<i32>,
-//     candidate_dir: &Coordinate<i32>,
-// ) {
-//     let mut loc = *candidate;
-//     let mut dir = *candidate_dir;
-//     let mut visited = visited.clone();
-//     let mut newly_visited = HashSet::new();
-//     loop {
-//         if visited.contains(&(loc, dir)) {
-//             break;
-//         }
-//         visited.insert((loc, dir));
-//         newly_visited.insert(loc);
-//         let next = loc + dir;
-//         match map.grid.get(&next) {
-//             None => break,
-//             Some(c) if c == &'#' => {
-//                 dir = rotate_dir(dir);
-//                 loc = loc + dir;
-//             }
-//             Some(_) => {
-//                 loc = next;
-//             }
-//         }
-//     }
-//     print_map(map, &visited, &newly_visited);
-// }
-
-fn tour_without_directions(map: &Map) -> HashSet<Coordinate<i32>> {
-    let mut visited = HashSet::new();
-    let mut loc = map.start;
-    let mut dir = START_DIR;
-    loop {
-        visited.insert(loc);
-        let next = loc + dir;
-        match map.grid.get(&next) {
-            None => break,
-            Some(c) if c == &'#' => {
-                dir = rotate_dir(dir);
+    // Does the guard, starting at `cell` facing `d`, loop once `obstacle` is
+    // added?  The extra obstacle is checked against each jump rather than
+    // rebuilding the tables: it only matters when it's on the stretch walked.
+    fn loops_with(&self, mut cell: usize, mut d: usize, obstacle: usize) -> bool {
+        let block = self.coord(obstacle);
+        let mut seen = HashSet::new();
+        loop {
+            if !seen.insert((cell, d)) {
+                return true;
             }
-            Some(_) => {
-                loc = next;
+            let here = self.coord(cell);
+            let step = STEPS[d];
+            let ahead = (block.x - here.x) * step.x + (block.y - here.y) * step.y;
+            let in_line =
+                here + Coordinate {
+                    x: step.x * ahead,
+                    y: step.y * ahead,
+                } == block;
+            let stop = self.jump[d][cell];
+            let reach = if stop == EXIT {
+                i32::MAX
+            } else {
+                let there = self.coord(stop);
+                (there.x - here.x) * step.x + (there.y - here.y) * step.y
+            };
+            if in_line && ahead > 0 && ahead <= reach {
+                cell = self.index(block - step).unwrap();
+            } else if stop == EXIT {
+                return false;
+            } else {
+                cell = stop;
             }
+            d = (d + 1) % 4;
         }
     }
-    visited
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Outcome {
+    Exits,
+    // the repeating stretch of the walk, as (position, heading)
+    Loops {
+        cycle: Vec<(Coordinate<i32>, Coordinate<i32>)>,
+    },
+}
+
+// How many cells a loop walks through before it repeats.  A turn on the spot
+// is a state of its own in the cycle but not a move.
+fn loop_moves(cycle: &[(Coordinate<i32>, Coordinate<i32>)]) -> usize {
+    (0..cycle.len())
+        .filter(|&i| cycle[i].0 != cycle[(i + 1) % cycle.len()].0)
+        .count()
+}
+
+#[derive(Clone, Debug)]
+struct Patrol {
+    visited: HashSet<Coordinate<i32>>,
+    outcome: Outcome,
+}
+
+// Each guard's patrol, plus every cell any of them stepped on.
+fn patrol_all(map: &Map) -> (Vec<Patrol>, HashSet<Coordinate<i32>>) {
+    let sim = Sim::new(map);
+    let patrols: Vec<Patrol> = map.guards.iter().map(|&g| sim.patrol(g)).collect();
+    let visited = patrols
+        .iter()
+        .flat_map(|p| p.visited.iter().copied())
+        .collect();
+    (patrols, visited)
+}
+
+// Obstructions that would trap one guard.  Each candidate is only walked
+// from the moment she would first bump into it; everything before that is
+// the same as the unobstructed walk.  No obstruction goes where any guard
+// starts.
+fn guard_blocks(map: &Map, sim: &Sim, guard: Guard) -> Vec<Coordinate<i32>> {
+    let mut blocks: Vec<Coordinate<i32>> = sim
+        .first_visits(guard)
+        .par_iter()
+        .filter(|&&(cell, d, candidate)| sim.loops_with(cell, d, candidate))
+        .map(|&(_, _, candidate)| sim.coord(candidate))
+        .filter(|c| map.guards.iter().all(|g| g.start != *c))
+        .collect();
+    blocks.sort_by_key(|c| (c.y, c.x));
+    blocks
+}
+
+// Obstructions that would trap at least one of the guards (none, if there
+// are no guards).
 fn plausible_blocks(map: &Map) -> HashSet<Coordinate<i32>> {
-    let mut visited = tour_without_directions(&map);
-    visited.remove(&map.start);
+    let sim = Sim::new(map);
+    map.guards
+        .iter()
+        .flat_map(|&guard| guard_blocks(map, &sim, guard))
+        .collect()
+}
+
+#[derive(Clone, Debug)]
+struct ObstructionLoop {
+    guard: Guard,
+    obstruction: Coordinate<i32>,
+    // the loop as Outcome::Loops reports it; the first step is where the
+    // guard joins it
+    cycle: Vec<(Coordinate<i32>, Coordinate<i32>)>,
+}
 
-    visited
+impl ObstructionLoop {
+    fn entry(&self) -> (Coordinate<i32>, Coordinate<i32>) {
+        self.cycle[0]
+    }
+
+    fn moves(&self) -> usize {
+        loop_moves(&self.cycle)
+    }
+
+    fn cells(&self) -> HashSet<Coordinate<i32>> {
+        self.cycle.iter().map(|&(c, _)| c).collect()
+    }
+}
+
+// The loop each of `blocks` traps the guard in.  A block that lets her walk
+// off the map is left out.
+fn trapping_loops(sim: &Sim, guard: Guard, blocks: &[Coordinate<i32>]) -> Vec<ObstructionLoop> {
+    blocks
         .par_iter()
-        .flat_map(|&loc| {
-            if on_path_to_repeat_except(&map, loc) {
-                Some(loc)
-            } else {
-                None
-            }
-        })
+        .filter_map(
+            |&obstruction| match sim.patrol_with(guard, sim.index(obstruction)).outcome {
+                Outcome::Loops { cycle } => Some(ObstructionLoop {
+                    guard,
+                    obstruction,
+                    cycle,
+                }),
+                Outcome::Exits => None,
+            },
+        )
+        .collect()
+}
+
+// For each guard in turn, the loop each block that traps her puts her in,
+// top to bottom.
+fn obstruction_loops(map: &Map) -> Vec<ObstructionLoop> {
+    let sim = Sim::new(map);
+    map.guards
+        .iter()
+        .flat_map(|&guard| trapping_loops(&sim, guard, &guard_blocks(map, &sim, guard)))
         .collect()
 }
 
+// Draw the map with each loop's obstruction as `O` and arrows along the loop.
+// A cell walked in more than one direction, such as a corner, gets a `+`.
+fn render_loops(map: &Map, loops: &[ObstructionLoop]) -> String {
+    let mut headings: HashMap<Coordinate<i32>, HashSet<Coordinate<i32>>> = HashMap::new();
+    for l in loops {
+        for &(cell, heading) in &l.cycle {
+            headings.entry(cell).or_default().insert(heading);
+        }
+    }
+    let obstructions: HashSet<Coordinate<i32>> = loops.iter().map(|l| l.obstruction).collect();
+    let mut out = String::new();
+    for y in 0..map.height {
+        for x in 0..map.width {
+            let coord = Coordinate { x, y };
+            out.push(if obstructions.contains(&coord) {
+                'O'
+            } else {
+                match headings.get(&coord) {
+                    Some(h) if h.len() == 1 => {
+                        let heading = h.iter().next().unwrap();
+                        GUARDS[STEPS.iter().position(|s| s == heading).unwrap()]
+                    }
+                    Some(_) => '+',
+                    None => map.grid[&coord],
+                }
+            });
+        }
+        out.push('\n');
+    }
+    out
+}
+
 #[time_function]
 fn part2(data: &str) -> u32 {
-    let map = parse_map(&data);
+    let map = parse_map(data);
     let candidates: HashSet<Coordinate<i32>> = plausible_blocks(&map);
     candidates.len() as u32
 }
@@ -211,6 +380,40 @@ fn main() -> Result<(), Box<dyn Error>> {
     let data = get_daily_input(6, 2024)?;
     println!("part1: {}", part1(&data));
     println!("part2: {}", part2(&data));
+    if std::env::args().any(|arg| arg == "--loops") {
+        let map = parse_map(&data);
+        for l in obstruction_loops(&map) {
+            let (entry, heading) = l.entry();
+            println!(
+                "guard from {:?}, O at {:?}: loop of {} moves over {} cells, joined at {:?} heading {:?}",
+                l.guard.start,
+                l.obstruction,
+                l.moves(),
+                l.cells().len(),
+                entry,
+                heading
+            );
+            print!("{}", render_loops(&map, &[l]));
+        }
+    }
+    if std::env::args().any(|arg| arg == "--guards") {
+        let map = parse_map(&data);
+        let (patrols, _) = patrol_all(&map);
+        for (guard, patrol) in map.guards.iter().zip(patrols) {
+            let how = match patrol.outcome {
+                Outcome::Exits => "then leaves".to_string(),
+                Outcome::Loops { cycle } => {
+                    format!("then loops every {} moves", loop_moves(&cycle))
+                }
+            };
+            println!(
+                "guard at {:?} visits {} cells {}",
+                guard.start,
+                patrol.visited.len(),
+                how
+            );
+        }
+    }
 
     Ok(())
 }
@@ -233,7 +436,13 @@ mod tests {
     fn test_parse() {
         let map = parse_map(&TESTDATA);
         assert_eq!(map.grid.len(), 100);
-        assert_eq!(map.start, Coordinate { x: 4, y: 6 });
+        assert_eq!(
+            map.guards,
+            vec![Guard {
+                start: Coordinate { x: 4, y: 6 },
+                facing: 0
+            }]
+        );
         assert_eq!(map.width, 10);
         assert_eq!(map.height, 10);
         assert_eq!(map.grid.get(&Coordinate { x: 0, y: 0 }), Some(&'.'));
@@ -245,11 +454,71 @@ mod tests {
         assert_eq!(part1(&TESTDATA), 41);
     }
 
+    #[test]
+    fn test_patrol_all() {
+        let map = parse_map("#....\n...#<\n.>...\n..^..\n.....");
+        let (patrols, visited) = patrol_all(&map);
+        assert_eq!(patrols.len(), 3);
+        // '<' turns north at the '#' and leaves
+        assert_eq!(patrols[0].visited.len(), 2);
+        // '>' heads east and off the map
+        assert_eq!(patrols[1].outcome, Outcome::Exits);
+        assert_eq!(patrols[1].visited.len(), 4);
+        assert_eq!(visited.len(), 9);
+    }
+
+    #[test]
+    fn test_patrol_loop() {
+        let map = parse_map(".#...\n....#\n.^...\n#....\n...#.");
+        let (patrols, _) = patrol_all(&map);
+        match &patrols[0].outcome {
+            Outcome::Loops { cycle } => {
+                // turning on the spot is a state of its own, so the four corners
+                // appear twice, but it isn't a move
+                assert_eq!(cycle.len(), 12);
+                assert_eq!(loop_moves(cycle), 8);
+                let cells: HashSet<Coordinate<i32>> = cycle.iter().map(|&(c, _)| c).collect();
+                assert_eq!(cells.len(), 8);
+                assert_eq!(patrols[0].visited.len(), 8);
+            }
+            other => panic!("expected a loop, got {:?}", other),
+        }
+    }
+
     #[test]
     fn test_part2() {
         assert_eq!(part2(&TESTDATA), 6);
     }
 
+    #[test]
+    fn test_obstruction_loops() {
+        let map = parse_map(&TESTDATA);
+        let loops = obstruction_loops(&map);
+        assert_eq!(loops.len(), 6);
+        let first = loops
+            .iter()
+            .find(|l| l.obstruction == Coordinate { x: 3, y: 6 })
+            .unwrap();
+        assert_eq!(first.entry(), (Coordinate { x: 4, y: 6 }, STEPS[0]));
+        assert_eq!(first.cycle.len(), 22);
+        assert_eq!(first.moves(), 18);
+        assert_eq!(first.cells().len(), 18);
+        assert_eq!(
+            render_loops(&map, std::slice::from_ref(first)),
+            "....#.....
+....+>>>+#
+....^...v.
+..#.^...v.
+....^..#v.
+....^...v.
+.#.O+<<<+.
+........#.
+#.........
+......#...
+"
+        );
+    }
+
     #[test]
     fn test_part2_carefully() {
         let map = parse_map(&TESTDATA);
@@ -261,4 +530,43 @@ mod tests {
         assert!(candidates.contains(&Coordinate { x: 7, y: 7 }));
         assert!(candidates.contains(&Coordinate { x: 7, y: 9 }));
     }
+
+    #[test]
+    fn test_trapping_loops() {
+        let map = parse_map(TESTDATA);
+        let sim = Sim::new(&map);
+        // (3,6) traps the guard, (0,0) is nowhere near her path
+        let blocks = [Coordinate { x: 0, y: 0 }, Coordinate { x: 3, y: 6 }];
+        let loops = trapping_loops(&sim, map.guards[0], &blocks);
+        assert_eq!(loops.len(), 1);
+        assert_eq!(loops[0].obstruction, Coordinate { x: 3, y: 6 });
+    }
+
+    #[test]
+    fn test_guards() {
+        // no guard, nothing to trap
+        let map = parse_map(&TESTDATA.replace('^', "."));
+        assert!(plausible_blocks(&map).is_empty());
+        assert!(obstruction_loops(&map).is_empty());
+        // a second guard: every block that traps either of them, checked by
+        // walking each guard past each obstruction
+        let map = parse_map(&TESTDATA.replace(".........#", "......v..#"));
+        assert_eq!(map.guards.len(), 2);
+        let sim = Sim::new(&map);
+        let mut expected = HashSet::new();
+        for (&c, &letter) in &map.grid {
+            if letter != '.' {
+                continue;
+            }
+            for &guard in &map.guards {
+                if let Outcome::Loops { .. } = sim.patrol_with(guard, sim.index(c)).outcome {
+                    expected.insert(c);
+                }
+            }
+        }
+        assert_eq!(plausible_blocks(&map), expected);
+        let loops = obstruction_loops(&map);
+        assert!(loops.iter().any(|l| l.guard == map.guards[0]));
+        assert!(loops.iter().any(|l| l.guard == map.guards[1]));
+    }
 }
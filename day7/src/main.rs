@@ -1,96 +1,507 @@
 use aochelpers::get_daily_input;
 use code_timing_macros::time_function;
+use num_bigint::BigUint;
+use num_traits::{CheckedAdd, CheckedDiv, CheckedMul, CheckedSub, Zero};
 use rayon::prelude::*;
 use std::error::Error;
+use std::fmt;
+use std::ops::BitXor;
+use std::str::FromStr;
 
-fn parse_exprs(data: &str) -> Vec<(u64, Vec<u64>)> {
+// Integer types the calibration solver can run over: u64 for the usual
+// lines, BigUint for goals too big for it.
+trait Number:
+    Clone
+    + Ord
+    + Zero
+    + CheckedAdd
+    + CheckedSub
+    + CheckedMul
+    + CheckedDiv
+    + BitXor<Output = Self>
+    + From<u64>
+    + FromStr
+    + fmt::Display
+    + Send
+    + Sync
+{
+}
+
+impl<N> Number for N where
+    N: Clone
+        + Ord
+        + Zero
+        + CheckedAdd
+        + CheckedSub
+        + CheckedMul
+        + CheckedDiv
+        + BitXor<Output = Self>
+        + From<u64>
+        + FromStr
+        + fmt::Display
+        + Send
+        + Sync
+{
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum CalibrationError {
+    Parse(String),
+    // undoing `operator` on the way back from `goal` needed a number too big
+    // for the integer type
+    Overflow {
+        goal: String,
+        operator: String,
+    },
+    // working `operator` out forwards gave a number too big for the integer
+    // type
+    OverflowApplying {
+        left: String,
+        operator: String,
+        right: String,
+    },
+}
+
+impl fmt::Display for CalibrationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CalibrationError::Parse(line) => write!(f, "can't parse {:?}", line),
+            CalibrationError::Overflow { goal, operator } => {
+                write!(f, "overflow undoing {} from {}", operator, goal)
+            }
+            CalibrationError::OverflowApplying {
+                left,
+                operator,
+                right,
+            } => write!(f, "overflow working out {} {} {}", left, operator, right),
+        }
+    }
+}
+
+impl Error for CalibrationError {}
+
+impl CalibrationError {
+    fn is_overflow(&self) -> bool {
+        matches!(
+            self,
+            CalibrationError::Overflow { .. } | CalibrationError::OverflowApplying { .. }
+        )
+    }
+}
+
+// BigUint has no CheckedRem, so build one from what it does have
+fn checked_rem<N: Number>(a: &N, b: &N) -> Option<N> {
+    a.checked_sub(&a.checked_div(b)?.checked_mul(b)?)
+}
+
+// A line of the input, in the smallest integer type its numbers fit.  A
+// small line whose working out overflows is done again as a big one.
+#[derive(Debug, PartialEq, Eq)]
+enum Equation {
+    Small(u64, Vec<u64>),
+    Big(BigUint, Vec<BigUint>),
+}
+
+fn parse_line<N: FromStr>(line: &str) -> Option<(N, Vec<N>)> {
+    let (goal, rest) = line.split_once(':')?;
+    let nums = rest
+        .split_whitespace()
+        .map(|n| n.parse().ok())
+        .collect::<Option<Vec<N>>>()?;
+    if nums.is_empty() {
+        return None;
+    }
+    Some((goal.trim().parse().ok()?, nums))
+}
+
+fn parse_exprs(data: &str) -> Result<Vec<Equation>, CalibrationError> {
     data.lines()
         .map(|line| {
-            let (goal, rest) = line.split_once(':').expect("expected ':'");
-            let nums = rest
-                .split_whitespace()
-                .map(|n| n.parse().expect("integer elements"))
-                .collect();
-            (goal.parse().expect("integer goal"), nums)
+            if let Some((goal, nums)) = parse_line(line) {
+                Ok(Equation::Small(goal, nums))
+            } else if let Some((goal, nums)) = parse_line(line) {
+                Ok(Equation::Big(goal, nums))
+            } else {
+                Err(CalibrationError::Parse(line.to_string()))
+            }
         })
         .collect()
 }
 
-fn reach(goal: &u64, nums: &[u64]) -> bool {
-    let goal = *goal;
-    let nums = &nums[0..nums.len()];
-    if nums.len() == 1 {
-        return nums[0] == goal;
-    } else {
-        let last = nums.last().unwrap();
-        let a = if goal % nums.last().unwrap() == 0 {
-            reach(&(goal / last), &nums[0..nums.len() - 1])
-        } else {
-            false
-        };
-        let b = if goal >= *last {
-            reach(&(goal - last), &nums[0..nums.len() - 1])
-        } else {
-            false
-        };
-        return a || b;
+// What undoing an operator tells the backwards search about its left operand.
+enum Undo<N> {
+    Left(N),
+    Impossible,
+    // the left operand doesn't fit in N
+    Overflow,
+    // no inverse, so the search has to try the left side forwards
+    Unsupported,
+}
+
+// What applying an operator going forwards gives.
+enum Applied<N> {
+    Value(N),
+    // the result isn't an N, e.g. a negative difference
+    Impossible,
+    // the result is too big for N
+    Overflow,
+}
+
+impl<N> From<Option<N>> for Applied<N> {
+    // for the checked_* arithmetic, where None means it didn't fit
+    fn from(value: Option<N>) -> Self {
+        match value {
+            Some(value) => Applied::Value(value),
+            None => Applied::Overflow,
+        }
     }
 }
 
-fn reach_concat(goal: u64, nums: &[u64]) -> bool {
-    if nums.len() == 1 {
-        return nums[0] == goal;
-    } else {
-        let last = nums.last().unwrap();
+trait Operator<N>: Sync {
+    fn symbol(&self) -> &str;
+    fn apply(&self, left: &N, right: &N) -> Applied<N>;
+    fn undo(&self, _goal: &N, _right: &N) -> Undo<N> {
+        Undo::Unsupported
+    }
+}
 
-        let c = match goal.to_string().strip_suffix(&last.to_string()) {
-            Some(shorter) if shorter.len() > 0 => {
-                reach_concat(shorter.parse().unwrap(), &nums[0..nums.len() - 1])
+struct Plus;
+struct Times;
+struct Minus;
+struct Xor;
+struct Concat {
+    base: u64,
+}
+
+impl<N: Number> Operator<N> for Plus {
+    fn symbol(&self) -> &str {
+        "+"
+    }
+    fn apply(&self, left: &N, right: &N) -> Applied<N> {
+        left.checked_add(right).into()
+    }
+    fn undo(&self, goal: &N, right: &N) -> Undo<N> {
+        match goal.checked_sub(right) {
+            Some(left) => Undo::Left(left),
+            None => Undo::Impossible,
+        }
+    }
+}
+
+impl<N: Number> Operator<N> for Times {
+    fn symbol(&self) -> &str {
+        "*"
+    }
+    fn apply(&self, left: &N, right: &N) -> Applied<N> {
+        left.checked_mul(right).into()
+    }
+    fn undo(&self, goal: &N, right: &N) -> Undo<N> {
+        // anything times zero is zero, so there's no telling what the left was
+        if right.is_zero() {
+            return Undo::Unsupported;
+        }
+        match (checked_rem(goal, right), goal.checked_div(right)) {
+            (Some(rem), Some(left)) if rem.is_zero() => Undo::Left(left),
+            _ => Undo::Impossible,
+        }
+    }
+}
+
+impl<N: Number> Operator<N> for Minus {
+    fn symbol(&self) -> &str {
+        "-"
+    }
+    fn apply(&self, left: &N, right: &N) -> Applied<N> {
+        match left.checked_sub(right) {
+            Some(value) => Applied::Value(value),
+            None => Applied::Impossible,
+        }
+    }
+    fn undo(&self, goal: &N, right: &N) -> Undo<N> {
+        match goal.checked_add(right) {
+            Some(left) => Undo::Left(left),
+            None => Undo::Overflow,
+        }
+    }
+}
+
+impl<N: Number> Operator<N> for Xor {
+    fn symbol(&self) -> &str {
+        "^"
+    }
+    fn apply(&self, left: &N, right: &N) -> Applied<N> {
+        Applied::Value(left.clone() ^ right.clone())
+    }
+    fn undo(&self, goal: &N, right: &N) -> Undo<N> {
+        Undo::Left(goal.clone() ^ right.clone())
+    }
+}
+
+impl Concat {
+    // the power of the base that shifts a number left past `right`'s digits,
+    // or None if that doesn't fit in N
+    fn shift<N: Number>(&self, right: &N) -> Option<N> {
+        let base = N::from(self.base);
+        let mut shift = base.clone();
+        let mut rest = right.checked_div(&base)?;
+        while !rest.is_zero() {
+            shift = shift.checked_mul(&base)?;
+            rest = rest.checked_div(&base)?;
+        }
+        Some(shift)
+    }
+}
+
+impl<N: Number> Operator<N> for Concat {
+    fn symbol(&self) -> &str {
+        "||"
+    }
+    fn apply(&self, left: &N, right: &N) -> Applied<N> {
+        self.shift(right)
+            .and_then(|shift| left.checked_mul(&shift)?.checked_add(right))
+            .into()
+    }
+    fn undo(&self, goal: &N, right: &N) -> Undo<N> {
+        match self.shift(right) {
+            Some(shift) => match (checked_rem(goal, &shift), goal.checked_div(&shift)) {
+                (Some(rem), Some(left)) if rem == *right => Undo::Left(left),
+                _ => Undo::Impossible,
+            },
+            // the shift is bigger than any N, so only a zero can go in front
+            None if goal == right => Undo::Left(N::zero()),
+            None => Undo::Impossible,
+        }
+    }
+}
+
+const ADD_MUL: [&str; 2] = ["+", "*"];
+const ADD_MUL_CONCAT: [&str; 3] = ["+", "*", "||"];
+
+fn all_operators<N: Number>() -> [&'static dyn Operator<N>; 5] {
+    [&Plus, &Times, &Concat { base: 10 }, &Minus, &Xor]
+}
+
+// The operators with these symbols, in the order given.
+fn operators<N: Number>(symbols: &[&str]) -> Vec<&'static dyn Operator<N>> {
+    symbols
+        .iter()
+        .filter_map(|&symbol| all_operators().into_iter().find(|op| op.symbol() == symbol))
+        .collect()
+}
+
+// Walk every choice of operators (as indices into `ops`, left to right) that
+// makes `nums` evaluate to `goal`, working back from the last operand.  Stops
+// as soon as `found` returns false, and returns false if it was stopped.
+fn search<N: Number>(
+    ops: &[&dyn Operator<N>],
+    goal: &N,
+    nums: &[N],
+    chosen: &mut Vec<usize>,
+    found: &mut dyn FnMut(Vec<usize>) -> bool,
+) -> Result<bool, CalibrationError> {
+    let (last, rest) = nums.split_last().expect("at least one operand");
+    if rest.is_empty() {
+        return Ok(last != goal || found(chosen.iter().rev().copied().collect()));
+    }
+    for (i, op) in ops.iter().enumerate() {
+        let keep_going = match op.undo(goal, last) {
+            Undo::Left(left) => {
+                chosen.push(i);
+                let keep_going = search(ops, &left, rest, chosen, found);
+                chosen.pop();
+                keep_going?
+            }
+            Undo::Impossible => true,
+            Undo::Overflow => {
+                return Err(CalibrationError::Overflow {
+                    goal: goal.to_string(),
+                    operator: op.symbol().to_string(),
+                })
+            }
+            Undo::Unsupported => {
+                let mut prefix = Vec::new();
+                forward(
+                    ops,
+                    &rest[0],
+                    &rest[1..],
+                    &mut prefix,
+                    &mut |value, prefix| match op.apply(value, last) {
+                        Applied::Value(value) if value == *goal => Ok(found(
+                            prefix
+                                .iter()
+                                .copied()
+                                .chain(std::iter::once(i))
+                                .chain(chosen.iter().rev().copied())
+                                .collect(),
+                        )),
+                        Applied::Overflow => Err(overflow_applying(value, *op, last)),
+                        _ => Ok(true),
+                    },
+                )?
             }
-            None | Some(_) => false,
-        };
-        let a = if goal % nums.last().unwrap() == 0 {
-            reach_concat(goal / last, &nums[0..nums.len() - 1])
-        } else {
-            false
-        };
-        let b = if goal >= *last {
-            reach_concat(goal - last, &nums[0..nums.len() - 1])
-        } else {
-            false
         };
-        return c || a || b;
+        if !keep_going {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+// Called with each value and the operators that got there; false stops.
+type Visit<'a, N> = dyn FnMut(&N, &[usize]) -> Result<bool, CalibrationError> + 'a;
+
+fn overflow_applying<N: Number>(left: &N, op: &dyn Operator<N>, right: &N) -> CalibrationError {
+    CalibrationError::OverflowApplying {
+        left: left.to_string(),
+        operator: op.symbol().to_string(),
+        right: right.to_string(),
+    }
+}
+
+// Every value `nums` can evaluate to, starting from `value`, with the
+// operators that got there.  A value too big for N is an error rather than a
+// dead end, since a later operator might have brought it back down.
+fn forward<N: Number>(
+    ops: &[&dyn Operator<N>],
+    value: &N,
+    nums: &[N],
+    prefix: &mut Vec<usize>,
+    visit: &mut Visit<N>,
+) -> Result<bool, CalibrationError> {
+    let Some((next, rest)) = nums.split_first() else {
+        return visit(value, prefix);
+    };
+    for (i, op) in ops.iter().enumerate() {
+        match op.apply(value, next) {
+            Applied::Value(value) => {
+                prefix.push(i);
+                let keep_going = forward(ops, &value, rest, prefix, visit);
+                prefix.pop();
+                if !keep_going? {
+                    return Ok(false);
+                }
+            }
+            Applied::Impossible => {}
+            Applied::Overflow => return Err(overflow_applying(value, *op, next)),
+        }
+    }
+    Ok(true)
+}
+
+fn format_expr<N: Number>(ops: &[&dyn Operator<N>], nums: &[N], chosen: &[usize]) -> String {
+    let mut expr = nums[0].to_string();
+    for (&op, num) in chosen.iter().zip(&nums[1..]) {
+        expr += &format!(" {} {}", ops[op].symbol(), num);
+    }
+    expr
+}
+
+// An expression for `goal`, evaluated left to right, e.g. "81 * 40 + 27".
+fn solve<N: Number>(
+    ops: &[&dyn Operator<N>],
+    goal: &N,
+    nums: &[N],
+) -> Result<Option<String>, CalibrationError> {
+    let mut answer = None;
+    search(ops, goal, nums, &mut Vec::new(), &mut |chosen| {
+        answer = Some(format_expr(ops, nums, &chosen));
+        false
+    })?;
+    Ok(answer)
+}
+
+fn count_solutions<N: Number>(
+    ops: &[&dyn Operator<N>],
+    goal: &N,
+    nums: &[N],
+) -> Result<usize, CalibrationError> {
+    let mut count = 0;
+    search(ops, goal, nums, &mut Vec::new(), &mut |_| {
+        count += 1;
+        true
+    })?;
+    Ok(count)
+}
+
+impl Equation {
+    fn goal(&self) -> BigUint {
+        match self {
+            Equation::Small(goal, _) => BigUint::from(*goal),
+            Equation::Big(goal, _) => goal.clone(),
+        }
+    }
+
+    fn big(&self) -> Equation {
+        match self {
+            Equation::Small(goal, nums) => Equation::Big(
+                BigUint::from(*goal),
+                nums.iter().map(|&n| BigUint::from(n)).collect(),
+            ),
+            Equation::Big(goal, nums) => Equation::Big(goal.clone(), nums.clone()),
+        }
+    }
+
+    fn solve(&self, symbols: &[&str]) -> Result<Option<String>, CalibrationError> {
+        match self {
+            Equation::Small(goal, nums) => match solve(&operators(symbols), goal, nums) {
+                Err(e) if e.is_overflow() => self.big().solve(symbols),
+                answer => answer,
+            },
+            Equation::Big(goal, nums) => solve(&operators(symbols), goal, nums),
+        }
+    }
+
+    fn count_solutions(&self, symbols: &[&str]) -> Result<usize, CalibrationError> {
+        match self {
+            Equation::Small(goal, nums) => match count_solutions(&operators(symbols), goal, nums) {
+                Err(e) if e.is_overflow() => self.big().count_solutions(symbols),
+                count => count,
+            },
+            Equation::Big(goal, nums) => count_solutions(&operators(symbols), goal, nums),
+        }
     }
 }
 
+// The sum of the goals that some choice of the operators can reach.
+fn total_calibration(data: &str, symbols: &[&str]) -> Result<BigUint, CalibrationError> {
+    let exprs = parse_exprs(data)?;
+    let solvable = exprs
+        .par_iter()
+        .map(|eq| Ok(eq.solve(symbols)?.map(|_| eq.goal())))
+        .collect::<Result<Vec<_>, CalibrationError>>()?;
+    Ok(solvable.into_iter().flatten().sum())
+}
+
 #[time_function]
-fn part1(data: &str) -> u64 {
-    let exprs = parse_exprs(&data);
-    exprs
-        .iter()
-        .filter_map(|(goal, nums)| if reach(goal, nums) { Some(goal) } else { None })
-        .sum()
+fn part1(data: &str) -> Result<BigUint, CalibrationError> {
+    total_calibration(data, &ADD_MUL)
 }
 
 #[time_function]
-fn part2(data: &str) -> u64 {
-    let exprs = parse_exprs(&data);
-    exprs
-        .par_iter()
-        .filter_map(|(goal, nums)| {
-            if reach_concat(*goal, nums) {
-                Some(goal)
-            } else {
-                None
-            }
-        })
-        .sum()
+fn part2(data: &str) -> Result<BigUint, CalibrationError> {
+    total_calibration(data, &ADD_MUL_CONCAT)
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     let data = get_daily_input(7, 2024)?;
-    println!("part1: {}", part1(&data));
-    println!("part2: {}", part2(&data));
+    println!("part1: {}", part1(&data)?);
+    println!("part2: {}", part2(&data)?);
+
+    // e.g. `--ops +,*,^` shows how each equation works out with those operators
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(i) = args.iter().position(|arg| arg == "--ops") {
+        let symbols: Vec<&str> = args
+            .get(i + 1)
+            .ok_or("--ops needs a list")?
+            .split(',')
+            .collect();
+        for eq in parse_exprs(&data)? {
+            if let Some(expr) = eq.solve(&symbols)? {
+                let count = eq.count_solutions(&symbols)?;
+                println!("{} = {} ({} ways)", eq.goal(), expr, count);
+            }
+        }
+    }
 
     Ok(())
 }
@@ -108,13 +519,129 @@ mod tests {
 292: 11 6 16 20";
     use super::*;
 
+    // no inverse, so the search has to fall back on going forwards
+    struct Max;
+
+    impl<N: Number> Operator<N> for Max {
+        fn symbol(&self) -> &str {
+            "max"
+        }
+        fn apply(&self, left: &N, right: &N) -> Applied<N> {
+            Applied::Value(left.max(right).clone())
+        }
+    }
+
+    fn solve_u64(ops: &[&dyn Operator<u64>], goal: u64, nums: &[u64]) -> Option<String> {
+        solve(ops, &goal, nums).unwrap()
+    }
+
+    #[test]
+    fn test_solve() {
+        let add_mul = operators(&ADD_MUL);
+        assert_eq!(
+            solve_u64(&add_mul, 190, &[10, 19]),
+            Some("10 * 19".to_string())
+        );
+        assert_eq!(solve_u64(&add_mul, 83, &[17, 5]), None);
+        assert_eq!(count_solutions(&add_mul, &3267, &[81, 40, 27]), Ok(2));
+        // times zero can't be undone, so it's worked out forwards
+        assert_eq!(solve_u64(&add_mul, 0, &[5, 0]), Some("5 * 0".to_string()));
+        assert_eq!(count_solutions(&add_mul, &0, &[5, 0, 0]), Ok(3));
+        assert_eq!(solve_u64(&add_mul, 7, &[7, 0]), Some("7 + 0".to_string()));
+        assert_eq!(
+            solve_u64(&operators(&ADD_MUL_CONCAT), 7290, &[6, 8, 6, 15]),
+            Some("6 * 8 || 6 * 15".to_string())
+        );
+    }
+
+    #[test]
+    fn test_other_operators() {
+        assert_eq!(
+            solve_u64(&[&Minus], 5, &[10, 5]),
+            Some("10 - 5".to_string())
+        );
+        assert_eq!(
+            solve_u64(&[&Plus, &Xor], 9, &[10, 3]),
+            Some("10 ^ 3".to_string())
+        );
+        // 101 followed by 11 in binary
+        let binary: [&dyn Operator<u64>; 2] = [&Plus, &Concat { base: 2 }];
+        assert_eq!(
+            solve_u64(&binary, 0b10111, &[5, 3]),
+            Some("5 || 3".to_string())
+        );
+        let with_max: [&dyn Operator<u64>; 2] = [&Plus, &Max];
+        assert_eq!(
+            solve_u64(&with_max, 9, &[3, 7, 2]),
+            Some("3 max 7 + 2".to_string())
+        );
+        assert_eq!(count_solutions(&with_max, &9, &[3, 7, 2]), Ok(1));
+        assert_eq!(count_solutions(&with_max, &12, &[3, 7, 2]), Ok(1));
+    }
+
+    #[test]
+    fn test_big_equations() {
+        let data = "36893488147419103232: 4294967296 4294967296 2
+18446744073709551616: 1 8446744073709551616";
+        let exprs = parse_exprs(data).unwrap();
+        assert!(matches!(exprs[0], Equation::Big(..)));
+        assert_eq!(
+            exprs[0].solve(&ADD_MUL),
+            Ok(Some("4294967296 * 4294967296 * 2".to_string()))
+        );
+        assert_eq!(
+            exprs[1].solve(&ADD_MUL_CONCAT),
+            Ok(Some("1 || 8446744073709551616".to_string()))
+        );
+        assert_eq!(
+            total_calibration(data, &ADD_MUL_CONCAT),
+            Ok(BigUint::from(3u64) * BigUint::from(u64::MAX) + BigUint::from(3u64))
+        );
+    }
+
+    #[test]
+    fn test_overflow() {
+        let minus = operators::<u64>(&["-"]);
+        assert_eq!(
+            solve(&minus, &u64::MAX, &[5, 1]),
+            Err(CalibrationError::Overflow {
+                goal: u64::MAX.to_string(),
+                operator: "-".to_string()
+            })
+        );
+        // max has no inverse, so 2^63 * 4 is worked out forwards
+        let with_max: [&dyn Operator<u64>; 2] = [&Times, &Max];
+        assert_eq!(
+            solve(&with_max, &5, &[1 << 63, 4, 5]),
+            Err(CalibrationError::OverflowApplying {
+                left: (1u64 << 63).to_string(),
+                operator: "*".to_string(),
+                right: "4".to_string()
+            })
+        );
+        assert!(parse_exprs("12: 3 x").is_err());
+        // 2^32 * 2^32 overflows a u64 while trying "* 0", but the line is
+        // worked out again in big numbers rather than given up on
+        let exprs = parse_exprs("5: 4294967296 4294967296 0 5").unwrap();
+        assert!(matches!(exprs[0], Equation::Small(..)));
+        assert_eq!(
+            exprs[0].solve(&["*", "+"]),
+            Ok(Some("4294967296 * 4294967296 * 0 + 5".to_string()))
+        );
+        assert_eq!(exprs[0].count_solutions(&ADD_MUL), Ok(2));
+        assert_eq!(
+            total_calibration("5: 4294967296 4294967296 0 5\n190: 10 19", &ADD_MUL),
+            Ok(BigUint::from(195u64))
+        );
+    }
+
     #[test]
     fn test_part1() {
-        assert_eq!(part1(&TESTDATA), 3749);
+        assert_eq!(part1(TESTDATA), Ok(BigUint::from(3749u64)));
     }
 
     #[test]
     fn test_part2() {
-        assert_eq!(part2(&TESTDATA), 11387);
+        assert_eq!(part2(TESTDATA), Ok(BigUint::from(11387u64)));
     }
 }
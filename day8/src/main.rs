@@ -16,31 +16,265 @@ impl Map {
     }
 }
 
-// Antinodes of two antennae
-fn antinodes2(a: &Coordinate<i32>, b: &Coordinate<i32>) -> (Coordinate<i32>, Coordinate<i32>) {
-    let diff = *a - *b;
-    (*b - diff, *a + diff)
-}
-
-// All the antinodes of a set of antennae, filtered to be on the map
-fn antinodes(map: &Map, antennae: &HashSet<Coordinate<i32>>) -> HashSet<Coordinate<i32>> {
-    let mut answer = HashSet::new();
-    for a in antennae.iter() {
-        for b in antennae.iter() {
-            if a != b {
-                let (c, d) = antinodes2(a, b);
-                if map.contains(c) {
-                    answer.insert(c);
-                };
-                if map.contains(d) {
-                    answer.insert(d);
-                };
+// Where a pair of antennae makes antinodes along the line through them.
+#[derive(Clone, Debug)]
+enum Rule {
+    // points beyond the pair whose distances to the two antennae are in one
+    // of these ratios, e.g. (2, 1) for "one antenna twice as far away as the
+    // other"; points between the two don't count
+    Ratios(Vec<(i32, i32)>),
+    // every lattice point on the line
+    AnyMultiple,
+}
+
+#[derive(Clone, Debug)]
+struct Rules {
+    default: Rule,
+    by_frequency: HashMap<char, Rule>,
+}
+
+impl Rules {
+    fn uniform(rule: Rule) -> Self {
+        Rules {
+            default: rule,
+            by_frequency: HashMap::new(),
+        }
+    }
+
+    fn for_frequency(&self, frequency: char) -> &Rule {
+        self.by_frequency.get(&frequency).unwrap_or(&self.default)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct Antinode {
+    at: Coordinate<i32>,
+    frequency: char,
+    pair: (Coordinate<i32>, Coordinate<i32>),
+}
+
+fn gcd(a: i32, b: i32) -> i32 {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+// The antinodes of one pair that land on the map.  Points on the line are
+// a + k * step, where step is b - a reduced by its gcd so that no lattice
+// point in between is skipped; b itself is at k = g.
+fn pair_antinodes(
+    map: &Map,
+    rule: &Rule,
+    a: Coordinate<i32>,
+    b: Coordinate<i32>,
+) -> Vec<Coordinate<i32>> {
+    let diff = b - a;
+    let g = gcd(diff.x, diff.y);
+    let step = Coordinate {
+        x: diff.x / g,
+        y: diff.y / g,
+    };
+    let at = |k: i32| {
+        a + Coordinate {
+            x: step.x * k,
+            y: step.y * k,
+        }
+    };
+    match rule {
+        Rule::AnyMultiple => {
+            let mut k = 0;
+            while map.contains(at(k - 1)) {
+                k -= 1;
+            }
+            let mut answer = Vec::new();
+            while map.contains(at(k)) {
+                answer.push(at(k));
+                k += 1;
+            }
+            answer
+        }
+        Rule::Ratios(ratios) => {
+            // |p - a| : |p - b| = m : n puts p at a + t (b - a) for
+            // t = m / (m - n), which is outside the pair whichever way round
+            let mut ks = Vec::new();
+            for &(m, n) in ratios {
+                for (m, n) in [(m, n), (n, m)] {
+                    if m != n && (g * m) % (m - n) == 0 {
+                        ks.push(g * m / (m - n));
+                    }
+                }
+            }
+            ks.sort();
+            ks.dedup();
+            ks.into_iter()
+                .map(at)
+                .filter(|&p| map.contains(p))
+                .collect()
+        }
+    }
+}
+
+// Every antinode on the map, tagged with the frequency and the pair of
+// antennae that made it.
+fn antinodes(map: &Map, rules: &Rules) -> Vec<Antinode> {
+    let mut answer = Vec::new();
+    for (&frequency, antennae) in map.antennae.iter() {
+        let rule = rules.for_frequency(frequency);
+        let mut antennae: Vec<Coordinate<i32>> = antennae.iter().copied().collect();
+        antennae.sort_by_key(|c| (c.y, c.x));
+        for (i, &a) in antennae.iter().enumerate() {
+            for &b in &antennae[i + 1..] {
+                for at in pair_antinodes(map, rule, a, b) {
+                    answer.push(Antinode {
+                        at,
+                        frequency,
+                        pair: (a, b),
+                    });
+                }
             }
         }
     }
     answer
 }
 
+fn count_locations(map: &Map, rules: &Rules) -> usize {
+    antinodes(map, rules)
+        .iter()
+        .map(|n| n.at)
+        .collect::<HashSet<_>>()
+        .len()
+}
+
+// Antinodes kept up to date as antennae come and go, for trying out changes
+// to the map.  Each location remembers how many pairs of each frequency put an
+// antinode there, so an edit only has to look at the pairs it touches.
+struct Interference {
+    map: Map,
+    rules: Rules,
+    counts: HashMap<Coordinate<i32>, HashMap<char, usize>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct FrequencyReport {
+    frequency: char,
+    antennae: Vec<Coordinate<i32>>,
+    antinodes: Vec<Coordinate<i32>>,
+    // antinodes shared with other frequencies, and which ones
+    overlaps: Vec<(Coordinate<i32>, Vec<char>)>,
+}
+
+fn sorted(coords: impl IntoIterator<Item = Coordinate<i32>>) -> Vec<Coordinate<i32>> {
+    let mut coords: Vec<Coordinate<i32>> = coords.into_iter().collect();
+    coords.sort_by_key(|c| (c.y, c.x));
+    coords
+}
+
+impl Interference {
+    fn new(map: Map, rules: Rules) -> Self {
+        let mut counts: HashMap<Coordinate<i32>, HashMap<char, usize>> = HashMap::new();
+        for node in antinodes(&map, &rules) {
+            *counts
+                .entry(node.at)
+                .or_default()
+                .entry(node.frequency)
+                .or_default() += 1;
+        }
+        Interference { map, rules, counts }
+    }
+
+    fn locations(&self) -> usize {
+        self.counts.len()
+    }
+
+    // Add or take away the antinodes of every pair between `at` and the
+    // other antennae of its frequency.
+    fn update_pairs(&mut self, frequency: char, at: Coordinate<i32>, adding: bool) {
+        let rule = self.rules.for_frequency(frequency);
+        let others = self.map.antennae.get(&frequency).into_iter().flatten();
+        for &other in others.filter(|&&other| other != at) {
+            for node in pair_antinodes(&self.map, rule, at, other) {
+                let by_frequency = self.counts.entry(node).or_default();
+                let count = by_frequency.entry(frequency).or_default();
+                if adding {
+                    *count += 1;
+                } else {
+                    *count -= 1;
+                    if *count == 0 {
+                        by_frequency.remove(&frequency);
+                        if by_frequency.is_empty() {
+                            self.counts.remove(&node);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // false if it's off the map or already there
+    fn add_antenna(&mut self, frequency: char, at: Coordinate<i32>) -> bool {
+        if !self.map.contains(at) || !self.map.antennae.entry(frequency).or_default().insert(at) {
+            return false;
+        }
+        self.update_pairs(frequency, at, true);
+        true
+    }
+
+    // false if there was no such antenna
+    fn remove_antenna(&mut self, frequency: char, at: Coordinate<i32>) -> bool {
+        if !self
+            .map
+            .antennae
+            .get(&frequency)
+            .is_some_and(|a| a.contains(&at))
+        {
+            return false;
+        }
+        self.update_pairs(frequency, at, false);
+        let antennae = self.map.antennae.get_mut(&frequency).unwrap();
+        antennae.remove(&at);
+        if antennae.is_empty() {
+            self.map.antennae.remove(&frequency);
+        }
+        true
+    }
+
+    fn report(&self) -> Vec<FrequencyReport> {
+        let mut frequencies: Vec<char> = self.map.antennae.keys().copied().collect();
+        frequencies.sort();
+        frequencies
+            .into_iter()
+            .map(|frequency| {
+                let mine = sorted(
+                    self.counts
+                        .iter()
+                        .filter(|(_, by_frequency)| by_frequency.contains_key(&frequency))
+                        .map(|(&at, _)| at),
+                );
+                let overlaps = mine
+                    .iter()
+                    .filter_map(|at| {
+                        let mut others: Vec<char> = self.counts[at]
+                            .keys()
+                            .copied()
+                            .filter(|&f| f != frequency)
+                            .collect();
+                        others.sort();
+                        (!others.is_empty()).then_some((*at, others))
+                    })
+                    .collect();
+                FrequencyReport {
+                    frequency,
+                    antennae: sorted(self.map.antennae[&frequency].iter().copied()),
+                    antinodes: mine,
+                    overlaps,
+                }
+            })
+            .collect()
+    }
+}
+
 fn parse_map(data: &str) -> Map {
     let grid = parse_number_grid(&data);
     let width = grid.keys().map(|c| c.x).max().unwrap() + 1;
@@ -61,53 +295,75 @@ fn parse_map(data: &str) -> Map {
 
 #[time_function]
 fn part1(data: &str) -> usize {
-    let map = parse_map(&data);
-    let mut all_antinodes: HashSet<Coordinate<i32>> = HashSet::new();
-    for (_freq, antennae) in map.antennae.iter() {
-        all_antinodes.extend(antinodes(&map, antennae));
-    }
-    all_antinodes.len()
-}
-
-fn slopenodes(map: &Map, antennae: &HashSet<Coordinate<i32>>) -> HashSet<Coordinate<i32>> {
-    let mut answer = HashSet::new();
-    for a in antennae.iter() {
-        for b in antennae.iter() {
-            if a != b {
-                // find all points on the line through a,b but within bounds
-                let slope = *a - *b;
-                let mut candidate = *a;
-                while map.contains(candidate) {
-                    candidate = candidate - slope;
-                }
-                loop {
-                    candidate = candidate + slope;
-                    if map.contains(candidate) {
-                        answer.insert(candidate);
-                    } else {
-                        break;
-                    }
-                }
-            }
-        }
-    }
-    answer
+    let map = parse_map(data);
+    count_locations(&map, &Rules::uniform(Rule::Ratios(vec![(2, 1)])))
 }
 
 #[time_function]
 fn part2(data: &str) -> usize {
-    let map = parse_map(&data);
-    let mut all_antinodes: HashSet<Coordinate<i32>> = HashSet::new();
-    for (_freq, antennae) in map.antennae.iter() {
-        all_antinodes.extend(slopenodes(&map, antennae));
-    }
-    all_antinodes.len()
+    let map = parse_map(data);
+    count_locations(&map, &Rules::uniform(Rule::AnyMultiple))
+}
+
+// "A,3,4" is an A antenna at x = 3, y = 4
+fn parse_antenna(text: &str) -> Option<(char, Coordinate<i32>)> {
+    let mut parts = text.split(',');
+    let mut frequency = parts.next()?.chars();
+    let frequency = frequency.next().filter(|_| frequency.next().is_none())?;
+    let x = parts.next()?.parse().ok()?;
+    let y = parts.next()?.parse().ok()?;
+    parts
+        .next()
+        .is_none()
+        .then_some((frequency, Coordinate { x, y }))
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     let data = get_daily_input(8, 2024)?;
     println!("part1: {}", part1(&data));
     println!("part2: {}", part2(&data));
+    // `--report` lists every frequency's antinodes, after trying out any
+    // `--add A,3,4` or `--remove 0,8,1` edits
+    let args: Vec<String> = std::env::args().collect();
+    if args.iter().any(|arg| arg == "--report") {
+        let mut interference =
+            Interference::new(parse_map(&data), Rules::uniform(Rule::AnyMultiple));
+        for pair in args.windows(2) {
+            let adding = match pair[0].as_str() {
+                "--add" => true,
+                "--remove" => false,
+                _ => continue,
+            };
+            let (frequency, at) = parse_antenna(&pair[1]).ok_or("expected frequency,x,y")?;
+            let before = interference.locations();
+            let changed = if adding {
+                interference.add_antenna(frequency, at)
+            } else {
+                interference.remove_antenna(frequency, at)
+            };
+            if changed {
+                println!(
+                    "{}: {} -> {} locations",
+                    pair[1],
+                    before,
+                    interference.locations()
+                );
+            } else {
+                println!("{}: nothing to change", pair[1]);
+            }
+        }
+        for r in interference.report() {
+            println!(
+                "{}: {} antennae, {} antinodes",
+                r.frequency,
+                r.antennae.len(),
+                r.antinodes.len()
+            );
+            for (at, others) in r.overlaps {
+                println!("  ({}, {}) shared with {:?}", at.x, at.y, others);
+            }
+        }
+    }
 
     Ok(())
 }
@@ -130,11 +386,105 @@ mod tests {
 
     #[test]
     fn test_a2() {
+        let map = parse_map(TESTDATA);
         let a = Coordinate { x: 4, y: 3 };
         let b = Coordinate { x: 5, y: 5 };
-        let (c, d) = antinodes2(&a, &b);
-        assert_eq!(d, Coordinate { x: 3, y: 1 });
-        assert_eq!(c, Coordinate { x: 6, y: 7 });
+        let found = pair_antinodes(&map, &Rule::Ratios(vec![(2, 1)]), a, b);
+        assert_eq!(
+            found,
+            vec![Coordinate { x: 3, y: 1 }, Coordinate { x: 6, y: 7 }]
+        );
+    }
+
+    #[test]
+    fn test_reduced_step() {
+        let map = parse_map(TESTDATA);
+        let a = Coordinate { x: 0, y: 0 };
+        let b = Coordinate { x: 4, y: 2 };
+        let line = pair_antinodes(&map, &Rule::AnyMultiple, a, b);
+        // (2, 1) sits between the two and mustn't be skipped
+        assert_eq!(line.len(), 6);
+        assert!(line.contains(&Coordinate { x: 2, y: 1 }));
+        // with a gap of 3, the points a third of the way along are 2:1 too,
+        // but only the ones beyond the pair are antinodes
+        let b = Coordinate { x: 3, y: 3 };
+        let found = pair_antinodes(&map, &Rule::Ratios(vec![(2, 1)]), a, b);
+        assert_eq!(found, vec![Coordinate { x: 6, y: 6 }]);
+    }
+
+    #[test]
+    fn test_ratios_match_mirroring() {
+        // part 1 is each antenna mirrored through the other
+        let map = parse_map(TESTDATA);
+        let rule = Rule::Ratios(vec![(2, 1)]);
+        for (a, b) in [((0, 0), (3, 3)), ((4, 3), (5, 5)), ((6, 0), (0, 9))] {
+            let a = Coordinate { x: a.0, y: a.1 };
+            let b = Coordinate { x: b.0, y: b.1 };
+            let mut mirrored: Vec<Coordinate<i32>> = [a - (b - a), b + (b - a)]
+                .into_iter()
+                .filter(|&p| map.contains(p))
+                .collect();
+            mirrored.sort_by_key(|p| (p.x - a.x) * (b.x - a.x) + (p.y - a.y) * (b.y - a.y));
+            assert_eq!(pair_antinodes(&map, &rule, a, b), mirrored);
+        }
+    }
+
+    #[test]
+    fn test_per_frequency_rules() {
+        let map = parse_map(TESTDATA);
+        let mut rules = Rules::uniform(Rule::Ratios(vec![(2, 1)]));
+        rules.by_frequency.insert('A', Rule::AnyMultiple);
+        let found = antinodes(&map, &rules);
+        assert!(found.contains(&Antinode {
+            at: Coordinate { x: 3, y: 1 },
+            frequency: '0',
+            pair: (Coordinate { x: 5, y: 2 }, Coordinate { x: 7, y: 3 }),
+        }));
+        assert!(found.contains(&Antinode {
+            at: Coordinate { x: 7, y: 7 },
+            frequency: 'A',
+            pair: (Coordinate { x: 8, y: 8 }, Coordinate { x: 9, y: 9 }),
+        }));
+    }
+
+    #[test]
+    fn test_report() {
+        let map = parse_map(".aa..\n...b.\n...b.");
+        let interference = Interference::new(map, Rules::uniform(Rule::Ratios(vec![(2, 1)])));
+        let report = interference.report();
+        assert_eq!(report.len(), 2);
+        assert_eq!(report[0].frequency, 'a');
+        assert_eq!(
+            report[0].antinodes,
+            vec![Coordinate { x: 0, y: 0 }, Coordinate { x: 3, y: 0 }]
+        );
+        assert_eq!(
+            report[0].overlaps,
+            vec![(Coordinate { x: 3, y: 0 }, vec!['b'])]
+        );
+        assert_eq!(report[1].antennae.len(), 2);
+        assert_eq!(
+            report[1].overlaps,
+            vec![(Coordinate { x: 3, y: 0 }, vec!['a'])]
+        );
+    }
+
+    #[test]
+    fn test_what_if() {
+        for rule in [Rule::Ratios(vec![(2, 1)]), Rule::AnyMultiple] {
+            let rules = Rules::uniform(rule);
+            let mut interference = Interference::new(parse_map(TESTDATA), rules.clone());
+            let before = interference.locations();
+            let extra = Coordinate { x: 1, y: 10 };
+            assert!(interference.add_antenna('A', extra));
+            assert!(!interference.add_antenna('A', extra));
+            let mut map = parse_map(TESTDATA);
+            map.antennae.get_mut(&'A').unwrap().insert(extra);
+            assert_eq!(interference.locations(), count_locations(&map, &rules));
+            assert!(interference.remove_antenna('A', extra));
+            assert!(!interference.remove_antenna('A', extra));
+            assert_eq!(interference.locations(), before);
+        }
     }
 
     #[test]
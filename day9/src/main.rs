@@ -3,255 +3,376 @@ use code_timing_macros::time_function;
 //use rayon::prelude::*;
 //use slice_deque::SliceDeque;
 //use std::collections::VecDeque;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
 use std::error::Error;
+use std::fmt;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
-enum File {
-    Data { file_id: u64 },
-    Empty,
+// A run of blocks belonging to one file.  A file that's been broken up has
+// more than one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Piece {
+    id: u64,
+    start: u64,
+    length: u64,
 }
 
-enum File2 {
-    Data { file_id: u64, length: u64 },
-    Empty { length: u64 },
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Span {
+    start: u64,
+    length: u64,
 }
 
-impl File2 {
-    fn length(&self) -> u64 {
-        match self {
-            File2::Data { length, .. } => *length,
-            File2::Empty { length } => *length,
+// The disk as runs of file blocks, kept in order with no empty pieces and
+// no two neighbouring pieces of the same file.
+#[derive(Debug, Clone, PartialEq)]
+struct Disk {
+    size: u64,
+    pieces: Vec<Piece>,
+}
+
+impl Disk {
+    fn tidy(&mut self) {
+        self.pieces.retain(|p| p.length > 0);
+        self.pieces.sort_by_key(|p| p.start);
+        let mut tidied: Vec<Piece> = Vec::with_capacity(self.pieces.len());
+        for piece in self.pieces.drain(..) {
+            match tidied.last_mut() {
+                Some(last) if last.id == piece.id && last.start + last.length == piece.start => {
+                    last.length += piece.length
+                }
+                _ => tidied.push(piece),
+            }
         }
+        self.pieces = tidied;
     }
 
-    fn emptyp(&self) -> bool {
-        match self {
-            File2::Empty { .. } => true,
-            _ => false,
+    // the free space between (but not after) the files
+    fn gaps(&self) -> Vec<Span> {
+        let mut gaps = Vec::new();
+        let mut cursor = 0;
+        for piece in &self.pieces {
+            if piece.start > cursor {
+                gaps.push(Span {
+                    start: cursor,
+                    length: piece.start - cursor,
+                });
+            }
+            cursor = piece.start + piece.length;
         }
+        gaps
     }
-}
 
-type Disk = Vec<File>;
+    fn fragmentation(&self) -> Fragmentation {
+        let mut pieces_per_file: HashMap<u64, usize> = HashMap::new();
+        for piece in &self.pieces {
+            *pieces_per_file.entry(piece.id).or_default() += 1;
+        }
+        Fragmentation {
+            holes: self.gaps().len(),
+            split_files: pieces_per_file.values().filter(|&&n| n > 1).count(),
+        }
+    }
 
-type Disk2 = Vec<File2>;
+    // The puzzle's picture, e.g. 00...111...2...333.44.5555.6666.777.888899,
+    // with ids past 9 as letters and past 35 as '#'.
+    fn render(&self) -> String {
+        let mut strip = vec!['.'; self.size as usize];
+        for piece in &self.pieces {
+            let c = u32::try_from(piece.id)
+                .ok()
+                .and_then(|id| char::from_digit(id, 36))
+                .unwrap_or('#');
+            for block in piece.start..piece.start + piece.length {
+                strip[block as usize] = c;
+            }
+        }
+        strip.into_iter().collect()
+    }
+}
 
-fn length(s: &Disk2) -> usize {
-    s.iter().map(|file| file.length()).sum::<u64>() as usize
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum DiskMapError {
+    NotADigit { offset: usize, found: char },
+    // a file with no blocks would leave two runs of free space side by side
+    EmptyFile { offset: usize, id: u64 },
 }
 
-fn parse_disk_map(data: &str) -> Disk {
-    let mut disk_map = Disk::new();
-    let mut file_number = 0;
-    let mut empty_next = false;
-    for byte in data.chars() {
-        let length = byte.to_digit(10).unwrap();
-        if empty_next {
-            for _i in 0..length {
-                disk_map.push(File::Empty);
+impl fmt::Display for DiskMapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DiskMapError::NotADigit { offset, found } => {
+                write!(
+                    f,
+                    "expected a digit at offset {}, found {:?}",
+                    offset, found
+                )
             }
-            empty_next = false;
-        } else {
-            for _i in 0..length {
-                disk_map.push(File::Data {
-                    file_id: file_number,
-                });
+            DiskMapError::EmptyFile { offset, id } => {
+                write!(f, "file {} at offset {} has no blocks", id, offset)
             }
-            empty_next = true;
-            file_number += 1;
         }
     }
-    disk_map
 }
 
-fn parse_disk_map2(data: &str) -> Disk2 {
-    let mut disk_map = Disk2::new();
-    let mut file_id = 0;
+impl Error for DiskMapError {}
+
+// Digits alternate file, free, file, ...  Whitespace anywhere (the trailing
+// newline, or a map wrapped over several lines) is skipped.
+fn parse_disk_map(data: &str) -> Result<Disk, DiskMapError> {
+    let mut disk = Disk {
+        size: 0,
+        pieces: Vec::new(),
+    };
+    let mut id = 0;
     let mut empty_next = false;
-    for byte in data.chars() {
-        let length: u64 = byte.to_digit(10).unwrap().into();
-        if empty_next {
-            disk_map.push(File2::Empty { length });
-            empty_next = false;
-        } else {
-            disk_map.push(File2::Data { file_id, length });
-            empty_next = true;
-            file_id += 1;
+    for (offset, found) in data.char_indices() {
+        if found.is_whitespace() {
+            continue;
         }
+        let length: u64 = found
+            .to_digit(10)
+            .ok_or(DiskMapError::NotADigit { offset, found })?
+            .into();
+        if !empty_next {
+            if length == 0 {
+                return Err(DiskMapError::EmptyFile { offset, id });
+            }
+            disk.pieces.push(Piece {
+                id,
+                start: disk.size,
+                length,
+            });
+            id += 1;
+        }
+        disk.size += length;
+        empty_next = !empty_next;
     }
-    disk_map
+    Ok(disk)
 }
 
-fn emptiness(disk: &Disk) -> usize {
-    disk.iter()
-        .filter(|file| match file {
-            File::Empty => true,
-            _ => false,
-        })
-        .count()
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Fragmentation {
+    // gaps before the last file block
+    holes: usize,
+    // files that aren't in one piece
+    split_files: usize,
 }
 
-fn emptiness2(disk: &Disk2) -> usize {
-    disk.iter()
-        .map(|file| match file {
-            File2::Empty { length } => *length,
-            _ => 0,
-        })
-        .sum::<u64>() as usize
+// A way of compacting the disk.  Returns how many runs of blocks it moved.
+trait Strategy {
+    fn name(&self) -> &'static str;
+    fn compact(&self, disk: &mut Disk) -> usize;
 }
 
-fn pack(disk: &mut Disk) {
-    // move files from the end of the disk to fill empty space near the front.
-    let mut a = 0;
-    let mut b = disk.len() - 1;
-    let limit = b - emptiness(disk);
-    while a < b {
-        assert!(a <= limit);
-        match disk[a] {
-            File::Data { file_id: _ } => {
-                a += 1;
-            }
-            File::Empty => {
-                disk.swap(a, b);
-                a += 1;
-                while disk[b] == File::Empty {
-                    b -= 1;
+// Part 1: move blocks one at a time from the end into the leftmost free block.
+struct BlockFill;
+
+// Part 2, and variations: move whole files, highest id first, into a gap to
+// their left that fits them.
+struct LeftmostFit;
+struct BestFit;
+struct WorstFit;
+
+impl Strategy for BlockFill {
+    fn name(&self) -> &'static str {
+        "block fill"
+    }
+
+    // Works a run at a time: fill each gap, left to right, with blocks taken
+    // off the end of the last piece on the disk.
+    fn compact(&self, disk: &mut Disk) -> usize {
+        let mut moves = 0;
+        let mut placed = Vec::new();
+        for gap in disk.gaps() {
+            let mut at = gap.start;
+            let end = gap.start + gap.length;
+            while at < end {
+                let Some(last) = disk.pieces.last_mut() else {
+                    break;
+                };
+                if last.start <= at {
+                    break;
+                }
+                let n = last.length.min(end - at);
+                placed.push(Piece {
+                    id: last.id,
+                    start: at,
+                    length: n,
+                });
+                last.length -= n;
+                if last.length == 0 {
+                    disk.pieces.pop();
                 }
+                at += n;
+                moves += 1;
             }
         }
+        disk.pieces.extend(placed);
+        disk.tidy();
+        moves
     }
 }
 
-// on disk, find index i, assume that's empty, and replace that with *two* empties
-// such that the first is length `length` and the other is whatever's left
-fn split_empty_space(disk: &mut Disk2, i: usize, length: u64) {
-    let e = disk.get(i).unwrap().length();
-    assert!(e >= length);
-    if e == length {
-        return;
-    }
-    disk.remove(i);
-    disk.insert(i, File2::Empty { length });
-    disk.insert(i + 1, File2::Empty { length: e - length });
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Fit {
+    Leftmost,
+    Best,
+    Worst,
 }
 
-fn congeal(disk: &mut Disk2) {
-    // splice together all adjacent empty spaces
-    for i in 0..disk.len() - 1 {
-        let e = disk.get(i).unwrap();
-        let f = disk.get(i + 1).unwrap();
-        if e.emptyp() && f.emptyp() {
-            *disk = disk
-                .splice(
-                    i..i + 1,
-                    [File2::Empty {
-                        length: e.length() + f.length(),
-                    }],
-                )
-                .collect();
+// Gaps are kept in one min-heap of start positions per size, so the leftmost
+// gap of each size is always on top.  Moving a file only ever opens space to
+// the right of every file still to go, so that space never needs to go back
+// into the heaps.  Files already in pieces stay where they are.
+fn pack_files(disk: &mut Disk, fit: Fit) -> usize {
+    let gaps = disk.gaps();
+    let biggest = gaps.iter().map(|g| g.length).max().unwrap_or(0) as usize;
+    let mut heaps: Vec<BinaryHeap<Reverse<u64>>> = vec![BinaryHeap::new(); biggest + 1];
+    for gap in gaps {
+        heaps[gap.length as usize].push(Reverse(gap.start));
+    }
+    let mut pieces_per_file: HashMap<u64, usize> = HashMap::new();
+    for piece in &disk.pieces {
+        *pieces_per_file.entry(piece.id).or_default() += 1;
+    }
+    let mut order: Vec<usize> = (0..disk.pieces.len())
+        .filter(|&i| pieces_per_file[&disk.pieces[i].id] == 1)
+        .collect();
+    order.sort_by_key(|&i| Reverse(disk.pieces[i].id));
+
+    let mut moves = 0;
+    for i in order {
+        let file = &mut disk.pieces[i];
+        let mut fits = (file.length as usize..heaps.len()).filter_map(|size| {
+            heaps[size]
+                .peek()
+                .filter(|&&Reverse(start)| start < file.start)
+                .map(|&Reverse(start)| (size, start))
+        });
+        let chosen = match fit {
+            Fit::Leftmost => fits.min_by_key(|&(_, start)| start),
+            Fit::Best => fits.next(),
+            Fit::Worst => fits.next_back(),
+        };
+        if let Some((size, start)) = chosen {
+            heaps[size].pop();
+            let rest = size - file.length as usize;
+            if rest > 0 {
+                heaps[rest].push(Reverse(start + file.length));
+            }
+            file.start = start;
+            moves += 1;
         }
     }
+    disk.tidy();
+    moves
 }
 
-fn pack_last_file(disk: &mut Disk2) {
-    // move the last file to the front of the disk
-    let mut a = 0;
-    let mut b = disk.len() - 1;
-    // move b cursor back to a file
-    while disk[b].emptyp() {
-        b -= 1;
+impl Strategy for LeftmostFit {
+    fn name(&self) -> &'static str {
+        "leftmost fit"
     }
-    // move a cursor forward to an empty
-    while a < b && !disk[a].emptyp() {
-        a += 1;
-        if disk[a].length() <= disk[b].length() {
-            continue;
-        } else {
-            split_empty_space(disk, a, disk[b].length());
-            disk.swap(a, b);
-            break;
-        }
+    fn compact(&self, disk: &mut Disk) -> usize {
+        pack_files(disk, Fit::Leftmost)
     }
 }
 
-fn pack2(disk: &mut Disk2) {
-    println!("packing disk len {}", disk.len());
-    // move files from the end of the disk to fill empty space near the front.
-    let mut b = disk.len() - 1;
-    // walk the disk from the back.  for each file, walk the disk from the front to the file's existing location looking for an empty space.
-    // Finding that space, either swap them directly OR split the empty space and swap.
-    while b > 0 {
-        let mut a = 0;
-        while disk[b].emptyp() {
-            b -= 1;
-        }
-        while a < b && (!disk[a].emptyp() || disk[a].length() < disk[b].length()) {
-            a += 1;
-        }
-        if a >= b {
-            b -= 1;
-            continue;
-        }
-        if disk[a].length() != disk[b].length() {
-            split_empty_space(disk, a, disk[b].length());
-            b = b + 1; //we split extra empty space early on, so the later part moved!
-        }
-        disk.swap(a, b);
+impl Strategy for BestFit {
+    fn name(&self) -> &'static str {
+        "best fit"
+    }
+    fn compact(&self, disk: &mut Disk) -> usize {
+        pack_files(disk, Fit::Best)
     }
 }
 
-fn checksum(disk: &Disk) -> u64 {
-    let mut checksum = 0;
-    let mut index = 0;
-    for file in disk {
-        match file {
-            File::Data { file_id } => {
-                checksum += *file_id as u64 * index;
-            }
-            _ => {}
-        }
-        index += 1;
+impl Strategy for WorstFit {
+    fn name(&self) -> &'static str {
+        "worst fit"
+    }
+    fn compact(&self, disk: &mut Disk) -> usize {
+        pack_files(disk, Fit::Worst)
     }
-    checksum
 }
 
-fn checksum2(disk: &Disk2) -> u64 {
-    let mut checksum = 0;
-    let mut index = 0;
-    for file in disk {
-        match file {
-            File2::Data { file_id, length } => {
-                for _i in 0..*length {
-                    checksum += *file_id * index;
-                    index += 1;
-                }
-            }
-            File2::Empty { length } => {
-                index += length;
-            }
-        }
-    }
-    checksum
+const STRATEGIES: [&dyn Strategy; 4] = [&BlockFill, &LeftmostFit, &BestFit, &WorstFit];
+
+// Each piece adds id * (start + (start + 1) + ... + (start + length - 1)).
+// That outgrows a u64 long before the ids or positions do.
+fn checksum(disk: &Disk) -> u128 {
+    disk.pieces
+        .iter()
+        .map(|p| {
+            let (id, start, length) = (p.id as u128, p.start as u128, p.length as u128);
+            id * (start * length + length * length.saturating_sub(1) / 2)
+        })
+        .sum()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Report {
+    strategy: &'static str,
+    moves: usize,
+    before: Fragmentation,
+    after: Fragmentation,
+    checksum: u128,
+}
+
+fn run(strategy: &dyn Strategy, disk: &Disk) -> (Disk, Report) {
+    let mut compacted = disk.clone();
+    let moves = strategy.compact(&mut compacted);
+    let report = Report {
+        strategy: strategy.name(),
+        moves,
+        before: disk.fragmentation(),
+        after: compacted.fragmentation(),
+        checksum: checksum(&compacted),
+    };
+    (compacted, report)
 }
 
 #[time_function]
-fn part1(data: &str) -> u64 {
-    let mut disk = parse_disk_map(data);
-    pack(&mut disk);
-    checksum(&disk)
+fn part1(data: &str) -> Result<u128, DiskMapError> {
+    let (_, report) = run(&BlockFill, &parse_disk_map(data)?);
+    Ok(report.checksum)
 }
 
 #[time_function]
-fn part2(data: &str) -> u64 {
-    let mut disk = parse_disk_map2(data);
-    pack2(&mut disk);
-    checksum2(&disk)
+fn part2(data: &str) -> Result<u128, DiskMapError> {
+    let (_, report) = run(&LeftmostFit, &parse_disk_map(data)?);
+    Ok(report.checksum)
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
     let data = get_daily_input(9, 2024)?;
-    println!("part1: {}", part1(&data));
-    println!("part2: {}", part2(&data));
+    println!("part1: {}", part1(&data)?);
+    println!("part2: {}", part2(&data)?);
+
+    // `--compare` tries every strategy, and `--show` draws the results too
+    let show = std::env::args().any(|arg| arg == "--show");
+    if show || std::env::args().any(|arg| arg == "--compare") {
+        let disk = parse_disk_map(&data)?;
+        if show {
+            println!("{}", disk.render());
+        }
+        for strategy in STRATEGIES {
+            let (compacted, r) = run(strategy, &disk);
+            println!(
+                "{}: {} moves, holes {} -> {}, split files {} -> {}, checksum {}",
+                r.strategy,
+                r.moves,
+                r.before.holes,
+                r.after.holes,
+                r.before.split_files,
+                r.after.split_files,
+                r.checksum
+            );
+            if show {
+                println!("{}", compacted.render());
+            }
+        }
+    }
 
     Ok(())
 }
@@ -263,33 +384,120 @@ mod tests {
 
     #[test]
     fn test_parse() {
-        let disk_map = parse_disk_map(TESTDATA);
-        assert_eq!(disk_map.len(), 42);
+        let disk = parse_disk_map(TESTDATA).unwrap();
+        assert_eq!(disk.size, 42);
+        assert_eq!(disk.pieces.len(), 10);
+        assert_eq!(disk.gaps().len(), 8);
     }
 
     #[test]
-    fn test_parse2() {
-        let disk_map = parse_disk_map2(TESTDATA);
-        assert_eq!(disk_map.len(), 19);
+    fn test_parse_errors() {
+        assert_eq!(parse_disk_map("12345\n"), parse_disk_map("12345"));
+        assert_eq!(parse_disk_map("123\n45"), parse_disk_map("12345"));
+        assert_eq!(
+            parse_disk_map("12x45"),
+            Err(DiskMapError::NotADigit {
+                offset: 2,
+                found: 'x'
+            })
+        );
+        assert_eq!(
+            parse_disk_map("1 2 0 4"),
+            Err(DiskMapError::EmptyFile { offset: 4, id: 1 })
+        );
+    }
+
+    #[test]
+    fn test_large_ids() {
+        let disk = Disk {
+            size: 3,
+            pieces: vec![Piece {
+                id: u32::MAX as u64 + 5,
+                start: 1,
+                length: 2,
+            }],
+        };
+        assert_eq!(disk.render(), ".##");
+        assert_eq!(checksum(&disk), (u32::MAX as u128 + 5) * 3);
+        // a few hundred thousand files, far more blocks than that
+        let data = "9".repeat(400_001);
+        let disk = parse_disk_map(&data).unwrap();
+        assert_eq!(disk.pieces.last().unwrap().id, 200_000);
+        assert_eq!(disk.size, 9 * 400_001);
+        let (compacted, _) = run(&BlockFill, &disk);
+        assert_eq!(compacted.gaps().len(), 0);
+    }
+
+    #[test]
+    fn test_render() {
+        let disk = parse_disk_map(TESTDATA).unwrap();
+        assert_eq!(disk.render(), "00...111...2...333.44.5555.6666.777.888899");
+        let (block_fill, _) = run(&BlockFill, &disk);
+        assert_eq!(
+            block_fill.render(),
+            "0099811188827773336446555566.............."
+        );
+        let (leftmost, _) = run(&LeftmostFit, &disk);
+        assert_eq!(
+            leftmost.render(),
+            "00992111777.44.333....5555.6666.....8888.."
+        );
     }
 
     #[test]
     fn test_part1() {
-        assert_eq!(part1(&TESTDATA), 1928);
+        assert_eq!(part1(&TESTDATA).unwrap(), 1928);
     }
 
     #[test]
     fn test_checksum() {
-        assert_eq!(checksum(&parse_disk_map("233")), 18);
+        assert_eq!(checksum(&parse_disk_map("233").unwrap()), 18);
+    }
+
+    #[test]
+    fn test_fits() {
+        let disk = parse_disk_map("13121").unwrap();
+        assert_eq!(disk.render(), "0...1..2");
+        assert_eq!(run(&LeftmostFit, &disk).0.render(), "021.....");
+        assert_eq!(run(&BestFit, &disk).0.render(), "01...2..");
+        assert_eq!(run(&WorstFit, &disk).0.render(), "021.....");
     }
 
     #[test]
-    fn test_checksum2() {
-        assert_eq!(checksum2(&parse_disk_map2("233")), 18);
+    fn test_report() {
+        let disk = parse_disk_map(TESTDATA).unwrap();
+        let (_, report) = run(&BlockFill, &disk);
+        assert_eq!(report.before.holes, 8);
+        assert_eq!(report.before.split_files, 0);
+        assert_eq!(report.after.holes, 0);
+        assert_eq!(report.checksum, 1928);
+        let (_, report) = run(&LeftmostFit, &disk);
+        assert_eq!(report.moves, 4);
+        assert_eq!(report.after.split_files, 0);
+        assert_eq!(report.after.holes, 5);
+    }
+
+    #[test]
+    fn test_pack_files_large() {
+        let data: String = (0..200_000)
+            .map(|i| ["9", "1", "3", "0", "7", "2", "1", "8"][i % 8])
+            .collect();
+        let before = parse_disk_map(&data).unwrap();
+        let (after, _) = run(&LeftmostFit, &before);
+        // files only move left, and never onto each other
+        let moved: HashMap<u64, Piece> = after.pieces.iter().map(|p| (p.id, *p)).collect();
+        for old in &before.pieces {
+            assert_eq!(moved[&old.id].length, old.length);
+            assert!(moved[&old.id].start <= old.start);
+        }
+        assert!(after
+            .pieces
+            .windows(2)
+            .all(|w| w[0].start + w[0].length <= w[1].start));
     }
 
     #[test]
     fn test_part2() {
-        assert_eq!(part2(&TESTDATA), 2858);
+        assert_eq!(part2(&TESTDATA).unwrap(), 2858);
     }
 }